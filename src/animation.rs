@@ -0,0 +1,120 @@
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::thread::sleep;
+use std::time::Duration;
+use crate::grid::Position;
+use crate::netpbm;
+
+/*
+ * A single state of a simulation, drawable either as characters or as gray levels.
+ */
+pub trait Frame {
+    fn height(&self) -> usize;
+    fn width(&self) -> usize;
+    fn glyph(&self, position: Position) -> char;
+    fn max_value(&self) -> u32;
+    fn value(&self, position: Position) -> u32;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Output {
+    Text,
+    Pgm,
+    Playback(Duration),
+}
+
+fn text(frame: &impl Frame) -> String {
+    (0..frame.height())
+        .map(|i| (0..frame.width()).map(|j| frame.glyph((i, j))).collect::<String>() + "\n")
+        .collect()
+}
+
+/*
+ * Frames are numbered by the step that produced them, with step 0 being the initial state, and only the
+ * requested range is written. Text frames are headed by their step and PGM frames are written back to back,
+ * which the format allows. Playback redraws the terminal in place and waits between frames.
+ */
+pub fn write_frames<F: Frame>(writer: &mut impl Write, frames: impl Iterator<Item = F>, steps: RangeInclusive<usize>, output: Output) -> std::io::Result<()> {
+    let frames = frames
+        .enumerate()
+        .skip(*steps.start())
+        .take_while(|(step, _)| steps.contains(step));
+
+    for (step, frame) in frames {
+        match output {
+            Output::Text => write!(writer, "After step {}:\n{}\n", step, text(&frame))?,
+            Output::Pgm => write!(writer, "{}", netpbm::pgm(frame.height(), frame.width(), frame.max_value(), |position| frame.value(position)))?,
+            Output::Playback(delay) => {
+                write!(writer, "\x1b[2J\x1b[HAfter step {}:\n{}", step, text(&frame))?;
+                writer.flush()?;
+                sleep(delay);
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/*
+ * Convenience for runners, which return the animation as their answer.
+ */
+pub fn frames_to_string<F: Frame>(frames: impl Iterator<Item = F>, steps: RangeInclusive<usize>, output: Output) -> String {
+    let mut buffer = vec![];
+    write_frames(&mut buffer, frames, steps, output).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(u32);
+
+    impl Frame for Counter {
+        fn height(&self) -> usize {
+            1
+        }
+
+        fn width(&self) -> usize {
+            2
+        }
+
+        fn glyph(&self, (_, j): Position) -> char {
+            char::from_digit(self.0 + j as u32, 10).unwrap()
+        }
+
+        fn max_value(&self) -> u32 {
+            9
+        }
+
+        fn value(&self, (_, j): Position) -> u32 {
+            self.0 + j as u32
+        }
+    }
+
+    fn counters() -> impl Iterator<Item = Counter> {
+        (0..).map(Counter)
+    }
+
+    #[test]
+    fn text_frames_in_range() {
+        assert_eq!("After step 2:\n23\n\nAfter step 3:\n34\n\n", frames_to_string(counters(), 2..=3, Output::Text));
+    }
+
+    #[test]
+    fn pgm_frames() {
+        assert_eq!("P2\n2 1\n9\n0 1\nP2\n2 1\n9\n1 2\n", frames_to_string(counters(), 0..=1, Output::Pgm));
+    }
+
+    #[test]
+    fn playback_clears_screen() {
+        let playback = frames_to_string(counters(), 4..=5, Output::Playback(Duration::ZERO));
+
+        assert_eq!("\x1b[2J\x1b[HAfter step 4:\n45\n\x1b[2J\x1b[HAfter step 5:\n56\n", playback);
+    }
+
+    #[test]
+    fn stops_at_end_of_simulation() {
+        assert_eq!("After step 1:\n12\n\n", frames_to_string(counters().take(2), 1..=5, Output::Text));
+    }
+}
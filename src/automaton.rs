@@ -0,0 +1,248 @@
+use itertools::Itertools;
+use crate::grid::{Edges, Grid, Position};
+
+/*
+ * A cellular automaton is a grid of cells plus a rule for what lies beyond its edges. Steps are either a
+ * synchronous local rule, applied to every cell and the cells in its neighborhood at once, or any other
+ * function from one state to the next for simulations that don't fit that mould.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Boundary<T> {
+    Finite,
+    Wrapping,
+    Infinite(T),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Neighborhood {
+    offsets: Vec<(isize, isize)>,
+    radius: usize,
+}
+
+impl Neighborhood {
+    pub fn new(offsets: impl IntoIterator<Item = (isize, isize)>) -> Self {
+        let offsets: Vec<(isize, isize)> = offsets.into_iter().collect();
+        let radius = offsets
+            .iter()
+            .map(|(di, dj)| di.unsigned_abs().max(dj.unsigned_abs()))
+            .max()
+            .unwrap_or(0);
+
+        Neighborhood { offsets, radius }
+    }
+
+    pub fn moore() -> Self {
+        Neighborhood::new((-1..=1).cartesian_product(-1..=1).filter(|&delta| delta != (0, 0)))
+    }
+}
+
+/*
+ * Apart from `Steps`, the stop conditions are only checked after at least one step. A simulation that
+ * settles into a cycle longer than one step, such as a blinker, never comes to a state without change, and
+ * one that never reaches the value never has all cells equal to it, so neither condition bounds the number
+ * of steps on its own. Taking a bounded number of states from `evolve_until` does.
+ */
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Stop<T> {
+    Steps(usize),
+    NoChange,
+    AllEqual(T),
+}
+
+impl<T: Clone + Eq> Stop<T> {
+    fn reached(&self, steps: usize, previous: &Automaton<T>, state: &Automaton<T>) -> bool {
+        match self {
+            Stop::Steps(limit) => steps >= *limit,
+            Stop::NoChange => previous == state,
+            Stop::AllEqual(value) => {
+                let background = match &state.boundary {
+                    Boundary::Infinite(background) => background == value,
+                    _ => true,
+                };
+
+                background && state.grid.iter().all(|(_, cell)| cell == value)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Automaton<T> {
+    grid: Grid<T>,
+    boundary: Boundary<T>,
+}
+
+impl<T: Clone + Eq> Automaton<T> {
+    pub fn new(grid: Grid<T>, boundary: Boundary<T>) -> Self {
+        Automaton { grid, boundary }
+    }
+
+    pub fn grid(&self) -> &Grid<T> {
+        &self.grid
+    }
+
+    pub fn with_grid(&self, grid: Grid<T>) -> Self {
+        Automaton { grid, boundary: self.boundary.clone() }
+    }
+
+    fn edges(&self) -> Edges {
+        match self.boundary {
+            Boundary::Wrapping => Edges::Wrapping,
+            _ => Edges::Bounded,
+        }
+    }
+
+    /*
+     * Neighboring positions that lie on the grid, after wrapping if the boundary wraps.
+     */
+    pub fn neighbors<'a>(&'a self, position: Position, neighborhood: &'a Neighborhood) -> impl Iterator<Item = Position> + 'a {
+        neighborhood.offsets
+            .iter()
+            .filter_map(move |delta| self.grid.offset(position, *delta, self.edges()))
+    }
+
+    fn value(&self, (i, j): (isize, isize)) -> Option<T> {
+        let (height, width) = (self.grid.height() as isize, self.grid.width() as isize);
+
+        match &self.boundary {
+            _ if (0..height).contains(&i) && (0..width).contains(&j) => Some(self.grid[(i as usize, j as usize)].clone()),
+            Boundary::Finite => None,
+            Boundary::Wrapping => Some(self.grid[(i.rem_euclid(height) as usize, j.rem_euclid(width) as usize)].clone()),
+            Boundary::Infinite(background) => Some(background.clone()),
+        }
+    }
+
+    /*
+     * The rule sees each cell together with its neighbors in the order the neighborhood lists them, minus any
+     * that fall off a finite grid. On an infinite grid the cells within reach of the background can change
+     * too, so the grid grows by the neighborhood's radius and the background itself is stepped as a whole.
+     */
+    pub fn apply(&self, neighborhood: &Neighborhood, rule: impl Fn(&T, &[T]) -> T) -> Self {
+        let growth = match self.boundary {
+            Boundary::Infinite(_) => neighborhood.radius,
+            _ => 0,
+        };
+
+        let grid = Grid::from_fn(self.grid.height() + 2 * growth, self.grid.width() + 2 * growth, |(i, j)| {
+            let (i, j) = (i as isize - growth as isize, j as isize - growth as isize);
+            let neighbors: Vec<T> = neighborhood.offsets
+                .iter()
+                .filter_map(|(di, dj)| self.value((i + di, j + dj)))
+                .collect();
+
+            rule(&self.value((i, j)).unwrap(), &neighbors)
+        });
+
+        let boundary = match &self.boundary {
+            Boundary::Infinite(background) => Boundary::Infinite(rule(background, &vec![background.clone(); neighborhood.offsets.len()])),
+            boundary => boundary.clone(),
+        };
+
+        Automaton { grid, boundary }
+    }
+}
+
+/*
+ * All states of a simulation, starting with the initial one.
+ */
+pub fn evolve<S>(initial: S, mut step: impl FnMut(&S) -> S) -> impl Iterator<Item = S> {
+    std::iter::successors(Some(initial), move |state| Some(step(state)))
+}
+
+/*
+ * The initial state and every state after it, up to and including the first one at which the stop condition
+ * holds. No step is taken past that state.
+ */
+pub fn evolve_until<T: Clone + Eq>(initial: Automaton<T>, mut step: impl FnMut(&Automaton<T>) -> Automaton<T>, stop: Stop<T>) -> impl Iterator<Item = Automaton<T>> {
+    let done = stop == Stop::Steps(0);
+    let mut pending = Some((0, initial, done));
+
+    std::iter::from_fn(move || {
+        let (steps, state, done) = pending.take()?;
+
+        if !done {
+            let next = step(&state);
+            let done = stop.reached(steps + 1, &state, &next);
+            pending = Some((steps + 1, next, done));
+        }
+
+        Some(state)
+    })
+}
+
+/*
+ * Steps the automaton until the stop condition holds, returning the number of steps taken and the final state.
+ */
+pub fn run<T: Clone + Eq>(initial: Automaton<T>, step: impl FnMut(&Automaton<T>) -> Automaton<T>, stop: Stop<T>) -> (usize, Automaton<T>) {
+    evolve_until(initial, step, stop).enumerate().last().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn life(automaton: &Automaton<bool>) -> Automaton<bool> {
+        automaton.apply(&Neighborhood::moore(), |alive, neighbors| {
+            matches!((alive, neighbors.iter().filter(|n| **n).count()), (true, 2) | (_, 3))
+        })
+    }
+
+    fn parse(input: &str) -> Grid<bool> {
+        Grid::parse_with(input, |c| Some(c == '#')).unwrap()
+    }
+
+    #[test]
+    fn blinker_on_finite_grid() {
+        let blinker = Automaton::new(parse(".....\n..#..\n..#..\n..#..\n....."), Boundary::Finite);
+        let states: Vec<_> = evolve(blinker, life).take(3).collect();
+
+        assert_eq!(parse(".....\n.....\n.###.\n.....\n....."), *states[1].grid());
+        assert_eq!(states[0], states[2]);
+    }
+
+    #[test]
+    fn glider_wraps_around() {
+        let glider = Automaton::new(parse(".#...\n..#..\n###..\n.....\n....."), Boundary::Wrapping);
+        let (_, state) = run(glider.clone(), life, Stop::Steps(20));
+
+        assert_eq!(glider, state);
+    }
+
+    #[test]
+    fn infinite_grid_grows_and_steps_background() {
+        let invert = |automaton: &Automaton<bool>| automaton.apply(&Neighborhood::new([(0, 0)]), |_, neighbors| !neighbors[0]);
+        let (_, state) = run(Automaton::new(parse("#."), Boundary::Infinite(false)), invert, Stop::Steps(3));
+
+        assert_eq!(parse(".#"), *state.grid());
+        assert_eq!(Boundary::Infinite(true), state.boundary);
+
+        let spread = |automaton: &Automaton<bool>| automaton.apply(&Neighborhood::moore(), |alive, neighbors| *alive || neighbors.contains(&true));
+        let (steps, state) = run(Automaton::new(parse("#"), Boundary::Infinite(false)), spread, Stop::Steps(2));
+
+        assert_eq!(2, steps);
+        assert_eq!((5, 5), (state.grid().height(), state.grid().width()));
+        assert!(state.grid().iter().all(|(_, alive)| *alive));
+    }
+
+    #[test]
+    fn stop_conditions() {
+        let still_life = Automaton::new(parse("....\n.##.\n.##.\n...."), Boundary::Finite);
+        assert_eq!(1, run(still_life.clone(), life, Stop::NoChange).0);
+        assert_eq!(0, run(still_life, life, Stop::Steps(0)).0);
+
+        let dying = Automaton::new(parse("#..\n...\n..#"), Boundary::Finite);
+        assert_eq!((1, Automaton::new(parse("...\n...\n..."), Boundary::Finite)), run(dying.clone(), life, Stop::AllEqual(false)));
+        assert_eq!(2, evolve_until(dying, life, Stop::AllEqual(false)).count());
+        assert_eq!(2, evolve_until(Automaton::new(parse("..."), Boundary::Finite), life, Stop::AllEqual(false)).count());
+
+        let invert = |automaton: &Automaton<bool>| automaton.apply(&Neighborhood::new([(0, 0)]), |_, neighbors| !neighbors[0]);
+        assert_eq!(1, run(Automaton::new(parse("#"), Boundary::Infinite(true)), invert, Stop::AllEqual(false)).0);
+        assert_eq!(10, evolve_until(Automaton::new(parse("."), Boundary::Infinite(true)), invert, Stop::AllEqual(true)).take(10).count());
+    }
+
+    #[test]
+    fn oscillators_never_stop_changing() {
+        let blinker = Automaton::new(parse(".....\n..#..\n..#..\n..#..\n....."), Boundary::Finite);
+        assert_eq!(100, evolve_until(blinker, life, Stop::NoChange).take(100).count());
+    }
+}
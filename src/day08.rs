@@ -1,9 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::num::ParseIntError;
 use std::ops::{Add, AddAssign, Sub};
 use std::slice::Iter;
 use std::str::FromStr;
+use itertools::Itertools;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 enum Segment {
@@ -49,6 +52,18 @@ impl Segment {
         static SEGMENTS: [Segment; 7] = [ Segment::A, Segment::B, Segment::C, Segment::D, Segment::E, Segment::F, Segment::G ];
         SEGMENTS.iter()
     }
+
+    fn letter(&self) -> char {
+        match self {
+            Segment::A => 'a',
+            Segment::B => 'b',
+            Segment::C => 'c',
+            Segment::D => 'd',
+            Segment::E => 'e',
+            Segment::F => 'f',
+            Segment::G => 'g',
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash)]
@@ -210,44 +225,201 @@ fn part1(input: &Vec<([Signal; 10], [Signal; 4])>) -> usize {
 }
 
 
-#[aoc(day8, part2)]
-fn part2(input: &Vec<([Signal; 10], [Signal; 4])>) -> i32 {
-    let mut sum = 0;
-
-    for (examples, signals) in input {
-        let mut segment_mapping: HashMap<Segment, Segment> = HashMap::new();
-        let mut known: HashMap<i32, Signal> = HashMap::new();
-
-        known.insert(1, *examples.iter().find(|e| e.num_segments() == 2).unwrap());
-        known.insert(4, *examples.iter().find(|e| e.num_segments() == 4).unwrap());
-        known.insert(7, *examples.iter().find(|e| e.num_segments() == 3).unwrap());
-        known.insert(8, *examples.iter().find(|e| e.num_segments() == 7).unwrap());
-        segment_mapping.insert(Segment::A, (known[&7] - known[&1]).segments().into_iter().next().unwrap());
-        known.insert(3, *examples.iter().find(|e| e.num_segments() == 5 && **e + known[&1] == **e).unwrap());
-        segment_mapping.insert(Segment::G, (known[&3] - known[&4] - segment_mapping[&Segment::A]).segments().into_iter().next().unwrap());
-        segment_mapping.insert(Segment::D, (known[&3] - known[&1] - segment_mapping[&Segment::A] - segment_mapping[&Segment::G]).segments().into_iter().next().unwrap());
-        segment_mapping.insert(Segment::B, (known[&4] - known[&1] - segment_mapping[&Segment::D]).segments().into_iter().next().unwrap());
-        segment_mapping.insert(Segment::E, (known[&8] - known[&3] - segment_mapping[&Segment::B]).segments().into_iter().next().unwrap());
-        known.insert(6, *examples.iter().find(|e| e.num_segments() == 6 && **e + known[&1] != **e).unwrap());
-        segment_mapping.insert(Segment::C, (known[&8] - known[&6]).segments().into_iter().next().unwrap());
-        segment_mapping.insert(Segment::F, (known[&1] - segment_mapping[&Segment::C]).segments().into_iter().next().unwrap());
-        known.insert(0, known[&8] - segment_mapping[&Segment::D]);
-        known.insert(2, known[&8] - segment_mapping[&Segment::B] - segment_mapping[&Segment::F]);
-        known.insert(5, known[&8] - segment_mapping[&Segment::C] - segment_mapping[&Segment::E]);
-        known.insert(7, known[&1] + segment_mapping[&Segment::A]);
-        known.insert(9, known[&8] - segment_mapping[&Segment::E]);
-
-        let signal_mapping: HashMap<Signal, i32> = known
+#[derive(Debug)]
+pub enum WiringError {
+    Contradictory,
+    Ambiguous,
+}
+
+impl Display for WiringError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            WiringError::Contradictory => write!(f, "no wiring matches the patterns"),
+            WiringError::Ambiguous => write!(f, "more than one wiring matches the patterns"),
+        }
+    }
+}
+
+impl Error for WiringError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Wiring {
+    mapping: HashMap<Segment, Segment>,
+}
+
+impl Wiring {
+    fn rewire(&self, signal: &Signal) -> Signal {
+        signal
+            .segments()
+            .into_iter()
+            .fold(Signal::new(), |acc, segment| acc + self.mapping[&segment])
+    }
+
+    fn digit(&self, signal: &Signal) -> Option<i32> {
+        let rewired = self.rewire(signal);
+        (0..10).find(|digit| Signal::try_from(*digit).unwrap() == rewired)
+    }
+
+    fn value(&self, signals: &[Signal]) -> Option<i32> {
+        signals
             .iter()
-            .map(|(k, v)| (*v, *k))
-            .collect();
+            .try_fold(0, |acc, signal| Some(acc * 10 + self.digit(signal)?))
+    }
+}
 
-        sum += signals
+fn candidate_segments(patterns: &[Signal]) -> [u8; 7] {
+    let digits: Vec<Signal> = (0..10).map(|digit| Signal::try_from(digit).unwrap()).collect();
+    let mut candidates = [0b1111111; 7];
+
+    for pattern in patterns {
+        let (lit, unlit) = digits
             .iter()
-            .fold(0, |acc, signal| acc * 10 + signal_mapping[signal])
+            .filter(|digit| digit.num_segments() == pattern.num_segments())
+            .fold((0, 0), |(lit, unlit), digit| (lit | digit.signal, unlit | (!digit.signal & 0b1111111)));
+
+        for (wire, candidate) in Segment::iter().zip(candidates.iter_mut()) {
+            *candidate &= match pattern.is_set(*wire) {
+                true => lit,
+                false => unlit,
+            };
+        }
+    }
+
+    candidates
+}
+
+fn find_wirings(patterns: &[Signal], candidates: &[u8; 7], assigned: &mut Vec<Segment>, wirings: &mut Vec<Wiring>) {
+    if assigned.len() == 7 {
+        let wiring = Wiring { mapping: Segment::iter().copied().zip(assigned.iter().copied()).collect() };
+
+        if patterns.iter().all(|pattern| wiring.digit(pattern).is_some()) {
+            wirings.push(wiring);
+        }
+
+        return;
     }
 
-    sum
+    for segment in Segment::iter() {
+        if candidates[assigned.len()] & (*segment as u8) > 0 && !assigned.contains(segment) {
+            assigned.push(*segment);
+            find_wirings(patterns, candidates, assigned, wirings);
+            assigned.pop();
+        }
+    }
+}
+
+fn wirings(patterns: &[Signal]) -> Vec<Wiring> {
+    let mut wirings = vec![];
+    find_wirings(patterns, &candidate_segments(patterns), &mut vec![], &mut wirings);
+
+    wirings
+}
+
+fn solve_wiring(patterns: &[Signal]) -> Result<Wiring, WiringError> {
+    let mut wirings = wirings(patterns);
+
+    match wirings.len() {
+        0 => Err(WiringError::Contradictory),
+        1 => Ok(wirings.remove(0)),
+        _ => Err(WiringError::Ambiguous),
+    }
+}
+
+/*
+ * An entry only needs a unique wiring as far as its four output digits are concerned: when some of the
+ * patterns are duplicated, several wirings may fit, but as long as they all decode the output to the
+ * same value, that value is the answer. Any of the fitting wirings is returned along with it.
+ */
+
+fn solve_entry((examples, signals): &([Signal; 10], [Signal; 4])) -> Result<(Wiring, i32), WiringError> {
+    let patterns: Vec<Signal> = examples.iter().chain(signals.iter()).copied().collect();
+    let mut wirings = wirings(&patterns);
+    let values: HashSet<Option<i32>> = wirings.iter().map(|wiring| wiring.value(signals)).collect();
+
+    match values.into_iter().exactly_one() {
+        _ if wirings.is_empty() => Err(WiringError::Contradictory),
+        Ok(Some(value)) => Ok((wirings.remove(0), value)),
+        _ => Err(WiringError::Ambiguous),
+    }
+}
+
+fn decode(entry: &([Signal; 10], [Signal; 4])) -> Result<i32, WiringError> {
+    solve_entry(entry).map(|(_, value)| value)
+}
+
+fn render_lines(signal: &Signal) -> [String; 7] {
+    let lit = |segment: Segment| match signal.is_set(segment) {
+        true => segment.letter(),
+        false => '.',
+    };
+    let horizontal = |segment: Segment| format!(" {} ", lit(segment).to_string().repeat(4));
+    let vertical = |left: Segment, right: Segment| format!("{}    {}", lit(left), lit(right));
+
+    [
+        horizontal(Segment::A),
+        vertical(Segment::B, Segment::C),
+        vertical(Segment::B, Segment::C),
+        horizontal(Segment::D),
+        vertical(Segment::E, Segment::F),
+        vertical(Segment::E, Segment::F),
+        horizontal(Segment::G),
+    ]
+}
+
+fn render(signals: &[Signal]) -> String {
+    let digits: Vec<[String; 7]> = signals.iter().map(render_lines).collect();
+
+    (0..7)
+        .map(|line| digits.iter().map(|digit| digit[line].as_str()).join("  "))
+        .join("\n")
+}
+
+fn render_rewired(signals: &[Signal], wiring: &Wiring) -> String {
+    let rewired: Vec<Signal> = signals.iter().map(|signal| wiring.rewire(signal)).collect();
+
+    render(signals)
+        .lines()
+        .zip(render(&rewired).lines())
+        .map(|(scrambled, rewired)| format!("{}  |  {}", scrambled, rewired))
+        .join("\n")
+}
+
+#[aoc(day8, part2)]
+fn part2(input: &[([Signal; 10], [Signal; 4])]) -> Result<i32, WiringError> {
+    input
+        .iter()
+        .map(decode)
+        .sum()
+}
+
+#[aoc(day8, part2, wiring)]
+fn part2_wiring(input: &[([Signal; 10], [Signal; 4])]) -> String {
+    let wirings = input
+        .iter()
+        .map(|(examples, signals)| {
+            let patterns: Vec<Signal> = examples.iter().chain(signals.iter()).copied().collect();
+
+            match solve_wiring(&patterns) {
+                Ok(wiring) => Segment::iter().map(|wire| format!("{}→{}", wire.letter(), wiring.mapping[wire].letter())).join(" "),
+                Err(error) => error.to_string(),
+            }
+        })
+        .join("\n");
+
+    format!("\n\n{}", wirings)
+}
+
+#[aoc(day8, part2, render)]
+fn part2_render(input: &[([Signal; 10], [Signal; 4])]) -> String {
+    let rendered = input
+        .iter()
+        .map(|entry| match solve_entry(entry) {
+            Ok((wiring, value)) => format!("{}\n{}", value, render_rewired(&entry.1, &wiring)),
+            Err(error) => format!("{}\n{}", error, render(&entry.1)),
+        })
+        .join("\n\n");
+
+    format!("\n\n{}", rendered)
 }
 
 #[cfg(test)]
@@ -261,11 +433,100 @@ mod tests {
 
     #[test]
     fn part2_example1() {
-        assert_eq!(61229, part2(&parse(include_str!("../input/2021/day8.part2.test.61229.txt")).unwrap()));
+        assert_eq!(61229, part2(&parse(include_str!("../input/2021/day8.part2.test.61229.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part2_example2() {
-        assert_eq!(5353, part2(&parse("acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf").unwrap()));
+        assert_eq!(5353, part2(&parse("acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf").unwrap()).unwrap());
+    }
+
+    #[test]
+    fn decode_duplicated_patterns() {
+        let entries = parse("ab ab ab ab ab ab ab ab ab ab | ab ba ab ab\nab ab ab ab ab ab ab ab ab ab | acdeg ab ab ab").unwrap();
+
+        assert!(matches!(solve_wiring(&entries[0].0), Err(WiringError::Ambiguous)));
+        assert_eq!(1111, decode(&entries[0]).unwrap());
+        assert!(matches!(decode(&entries[1]), Err(WiringError::Ambiguous)));
+    }
+
+    #[test]
+    fn solve_wiring_example() {
+        let patterns: Vec<Signal> = "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab".split(' ').map(|s| s.parse().unwrap()).collect();
+        let expected = Wiring {
+            mapping: [
+                (Segment::D, Segment::A), (Segment::E, Segment::B), (Segment::A, Segment::C), (Segment::F, Segment::D),
+                (Segment::G, Segment::E), (Segment::B, Segment::F), (Segment::C, Segment::G),
+            ].into_iter().collect(),
+        };
+
+        assert_eq!(expected, solve_wiring(&patterns).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn wiring_per_entry() {
+        let entries = parse("acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf\nab ab ab ab ab ab ab ab ab ab | ab ba ab ab").unwrap();
+
+        assert_eq!("\n\na→c b→f c→g d→a e→b f→d g→e\nmore than one wiring matches the patterns", part2_wiring(&entries));
+    }
+
+    #[test]
+    fn solve_wiring_missing_patterns() {
+        let patterns: Vec<Signal> = "acedgfb cdfbe fbcad dab cefabd cdfgeb eafb ab".split(' ').map(|s| s.parse().unwrap()).collect();
+        assert_eq!(Some(5), solve_wiring(&patterns).unwrap().digit(&"cdfbe".parse().unwrap()));
+    }
+
+    #[test]
+    fn solve_wiring_ambiguous() {
+        let patterns: Vec<Signal> = "ab dab".split(' ').map(|s| s.parse().unwrap()).collect();
+        assert!(matches!(solve_wiring(&patterns), Err(WiringError::Ambiguous)));
+    }
+
+    #[test]
+    fn solve_wiring_contradictory() {
+        let patterns: Vec<Signal> = "ab cd".split(' ').map(|s| s.parse().unwrap()).collect();
+        assert!(matches!(solve_wiring(&patterns), Err(WiringError::Contradictory)));
+    }
+
+    #[test]
+    fn render_digits() {
+        let signals: Vec<Signal> = [1, 7].into_iter().map(|digit| Signal::try_from(digit).unwrap()).collect();
+        let expected = [
+            " ....    aaaa ",
+            ".    c  .    c",
+            ".    c  .    c",
+            " ....    .... ",
+            ".    f  .    f",
+            ".    f  .    f",
+            " ....    .... ",
+        ].join("\n");
+
+        assert_eq!(expected, render(&signals));
+    }
+
+    #[test]
+    fn render_rewired_example() {
+        let patterns: Vec<Signal> = "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab".split(' ').map(|s| s.parse().unwrap()).collect();
+        let wiring = solve_wiring(&patterns).unwrap();
+        let expected = [
+            " aaaa   |   aaaa ",
+            "b    .  |  .    c",
+            "b    .  |  .    c",
+            " dddd   |   .... ",
+            ".    .  |  .    f",
+            ".    .  |  .    f",
+            " ....   |   .... ",
+        ].join("\n");
+
+        assert_eq!(expected, render_rewired(&["dab".parse().unwrap()], &wiring));
+    }
+
+    #[test]
+    fn render_entries() {
+        let entries = parse("ab ab ab ab ab ab ab ab ab ab | ab ba ab ab\nab cd ab ab ab ab ab ab ab ab | ab ab ab ab").unwrap();
+        let rendered = part2_render(&entries);
+        let headers: Vec<&str> = rendered.split("\n\n").skip(1).map(|entry| entry.lines().next().unwrap()).collect();
+
+        assert_eq!(vec!["1111", "no wiring matches the patterns"], headers);
+    }
+}
@@ -1,96 +1,215 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::num::ParseIntError;
-
-fn parse_input(input: &str) -> Result<HashMap<(i32, i32), i32>, ParseIntError> {
-    let width = input.lines().next().unwrap().chars().count();
-
-    Ok(
-        input
-            .lines()
-            .flat_map(|line| line.chars())
-            .map(|c| c.to_digit(10).unwrap() as i32)
-            .enumerate()
-            .map(|(i, v)| (((i / width) as i32, (i % width) as i32), v))
-            .collect()
-    )
+use crate::animation::{frames_to_string, Frame, Output};
+use crate::automaton::{evolve, evolve_until, Automaton, Boundary, Neighborhood, Stop};
+use crate::grid::{Grid, GridError, Position};
+
+fn parse_input(input: &str) -> Result<Grid<u32>, GridError> {
+    Grid::parse_digits(input)
+}
+
+#[derive(Debug)]
+enum Day11Error {
+    InvalidSteps(String),
+    Grid(GridError),
+}
+
+impl Display for Day11Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Day11Error::InvalidSteps(steps) => write!(f, "invalid step count: {}", steps),
+            Day11Error::Grid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for Day11Error {}
+
+impl From<GridError> for Day11Error {
+    fn from(error: GridError) -> Self {
+        Day11Error::Grid(error)
+    }
 }
 
+/*
+ * The puzzle asks for 100 steps, but the input may start with a `steps: n` line to count a different number.
+ */
 #[aoc_generator(day11)]
-fn generate(input: &str) -> Result<(HashMap<(i32, i32), i32>, usize), ParseIntError> {
-    Ok((parse_input(input)?, 100))
+fn generate(input: &str) -> Result<(Grid<u32>, usize), Day11Error> {
+    match input.split_once('\n') {
+        Some((header, energy)) if header.starts_with("steps:") => {
+            let steps = header["steps:".len()..].trim();
+            let steps = steps.parse().map_err(|_| Day11Error::InvalidSteps(steps.to_string()))?;
+
+            Ok((parse_input(energy)?, steps))
+        },
+        _ => Ok((parse_input(input)?, 100)),
+    }
 }
 
-fn neighbors((i, j): &(i32, i32)) -> impl IntoIterator<Item = (i32, i32)> {
-    let (i, j) = (*i, *j);
+fn octopuses(energy: &Grid<u32>) -> Automaton<u32> {
+    Automaton::new(energy.clone(), Boundary::Finite)
+}
 
-    (0..9)
-        .map(|a| (a / 3 - 1, a % 3 - 1))
-        .map(move |(a, b)| (i + a, j + b))
-        .filter(move |(a, b)| !(*a == i && *b == j))
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct StepRecord {
+    flashed: Vec<Position>,
+    waves: usize,
 }
 
-fn simulate(energy: &HashMap<(i32, i32), i32>, max_steps: Option<usize>) -> (usize, usize) {
-    let mut energy = energy.clone();
-    let mut num_flashes = 0;
+impl StepRecord {
+    fn flashes(&self) -> usize {
+        self.flashed.len()
+    }
+}
 
-    for step in 0..max_steps.unwrap_or(usize::MAX) {
-        for (_, v) in &mut energy {
-            *v += 1;
-        }
+/*
+ * Flashes spread in waves: the octopuses whose energy the step itself pushes over 9 flash first, then those
+ * pushed over by the first wave, and so on. Each octopus joins a wave the moment its energy reaches exactly
+ * 10, so it can't flash twice, and only the neighbors of the previous wave need looking at.
+ */
+fn flash(octopuses: &Automaton<u32>) -> (Automaton<u32>, StepRecord) {
+    let neighborhood = Neighborhood::moore();
+    let mut energy = octopuses.grid().clone();
 
-        let mut positions_flashed: HashSet<(i32, i32)> = HashSet::new();
+    for v in energy.values_mut() {
+        *v += 1;
+    }
 
-        loop {
-            let mut positions_to_increment = vec![];
+    let mut wave: VecDeque<Position> = energy.iter().filter(|(_, v)| **v > 9).map(|(k, _)| k).collect();
+    let mut flashed = vec![];
+    let mut waves = 0;
 
-            for (k, v) in &energy {
-                if *v > 9 && !positions_flashed.contains(k) {
-                    for neighbor in neighbors(k) {
-                        positions_to_increment.push(neighbor);
-                    }
+    while !wave.is_empty() {
+        waves += 1;
 
-                    positions_flashed.insert(k.to_owned());
-                }
-            }
+        for _ in 0..wave.len() {
+            let k = wave.pop_front().unwrap();
 
-            if positions_to_increment.is_empty() {
-                break;
-            }
+            for neighbor in octopuses.neighbors(k, &neighborhood) {
+                energy[neighbor] += 1;
 
-            for k in positions_to_increment {
-                if let Some(v) = energy.get_mut(&k) {
-                    *v += 1;
+                if energy[neighbor] == 10 {
+                    wave.push_back(neighbor);
                 }
             }
-        }
 
-        for (k, v) in &mut energy {
-            if positions_flashed.contains(k) {
-                *v = 0;
-            }
+            flashed.push(k);
         }
+    }
+
+    for k in &flashed {
+        energy[*k] = 0;
+    }
+
+    (octopuses.with_grid(energy), StepRecord { flashed, waves })
+}
+
+fn step(octopuses: &Automaton<u32>) -> Automaton<u32> {
+    flash(octopuses).0
+}
+
+/*
+ * What happened during each step, starting with the first.
+ */
+fn history(energy: &Grid<u32>) -> impl Iterator<Item = StepRecord> {
+    let mut octopuses = octopuses(energy);
+
+    std::iter::from_fn(move || {
+        let (next, record) = flash(&octopuses);
+        octopuses = next;
+        Some(record)
+    })
+}
+
+fn total_flashes(energy: &Grid<u32>, steps: usize) -> usize {
+    history(energy).take(steps).map(|record| record.flashes()).sum()
+}
+
+fn first_synchronized(energy: &Grid<u32>) -> usize {
+    let size = energy.height() * energy.width();
+    history(energy).position(|record| record.flashes() == size).unwrap() + 1
+}
 
-        num_flashes += positions_flashed.len();
+/*
+ * The step from which the states repeat, and how many steps it takes them to come round again.
+ */
+fn cycle(energy: &Grid<u32>) -> (usize, usize) {
+    let mut seen: HashMap<Grid<u32>, usize> = HashMap::new();
 
-        if positions_flashed.len() == energy.len() {
-            return (step + 1, num_flashes);
+    for (step, octopuses) in states(energy).enumerate() {
+        if let Some(previous) = seen.insert(octopuses.grid().clone(), step) {
+            return (previous, step - previous);
         }
     }
 
-    (usize::MAX, num_flashes)
+    unreachable!()
+}
+
+fn states(energy: &Grid<u32>) -> impl Iterator<Item = Automaton<u32>> {
+    evolve(octopuses(energy), step)
+}
+
+/*
+ * Octopuses that just flashed are drawn brightest, the rest by their energy level.
+ */
+impl Frame for Automaton<u32> {
+    fn height(&self) -> usize {
+        self.grid().height()
+    }
+
+    fn width(&self) -> usize {
+        self.grid().width()
+    }
+
+    fn glyph(&self, position: Position) -> char {
+        char::from_digit(self.grid()[position], 10).unwrap()
+    }
+
+    fn max_value(&self) -> u32 {
+        9
+    }
+
+    fn value(&self, position: Position) -> u32 {
+        match self.grid()[position] {
+            0 => 9,
+            energy => energy - 1,
+        }
+    }
 }
 
 #[aoc(day11, part1)]
-fn part1((energy, steps): &(HashMap<(i32, i32), i32>, usize)) -> usize {
-    let (_, num_flashes) = simulate(energy, Some(*steps));
-    num_flashes
+fn part1((energy, steps): &(Grid<u32>, usize)) -> usize {
+    total_flashes(energy, *steps)
 }
 
 #[aoc(day11, part2)]
-fn part2((energy, _): &(HashMap<(i32, i32), i32>, usize)) -> usize {
-    let (steps_simulated, _) = simulate(energy, None);
-    steps_simulated
+fn part2((energy, _): &(Grid<u32>, usize)) -> usize {
+    first_synchronized(energy)
+}
+
+#[aoc(day11, part2, history)]
+fn part2_history((energy, _): &(Grid<u32>, usize)) -> String {
+    let synchronized = first_synchronized(energy);
+    let (cycle_start, period) = cycle(energy);
+    let (deepest_step, deepest) = history(energy)
+        .take(synchronized)
+        .enumerate()
+        .max_by_key(|(_, record)| record.waves)
+        .unwrap();
+
+    format!(
+        "first synchronized at step {}, repeating every {} steps from step {}; deepest cascade: {} waves at step {}",
+        synchronized, period, cycle_start, deepest.waves, deepest_step + 1,
+    )
+}
+
+#[aoc(day11, part2, frames)]
+fn part2_frames((energy, _): &(Grid<u32>, usize)) -> String {
+    let frames = evolve_until(octopuses(energy), step, Stop::AllEqual(0));
+    format!("\n\n{}", frames_to_string(frames, 0..=usize::MAX, Output::Text))
 }
 
 #[cfg(test)]
@@ -116,4 +235,53 @@ mod tests {
     fn part2_example() {
         assert_eq!(195, part2(&(parse_input(include_str!("../input/2021/day11.part2.test.195.txt")).unwrap(), 100)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn generated_step_count() {
+        let input = generate(include_str!("../input/2021/day11.part1.test.1656.txt")).unwrap();
+
+        assert_eq!(100, input.1);
+        assert_eq!(1656, part1(&input));
+        assert_eq!(total_flashes(&input.0, 10), part1(&(input.0.clone(), 10)));
+        assert_eq!(0, part1(&(input.0, 0)));
+
+        let input = generate(&format!("steps: 10\n{}", include_str!("../input/2021/day11.part1.test.1656.txt"))).unwrap();
+
+        assert_eq!(10, input.1);
+        assert_eq!(204, part1(&input));
+        assert!(matches!(generate("steps: ten\n11\n11"), Err(Day11Error::InvalidSteps(steps)) if steps == "ten"));
+        assert!(matches!(generate("steps: 10\n1x\n11"), Err(Day11Error::Grid(GridError::InvalidCell('x', (0, 1))))));
+    }
+
+    #[test]
+    fn frames_until_synchronized() {
+        let frames = part2_frames(&generate(include_str!("../input/2021/day11.part1.test.9.txt")).unwrap());
+
+        assert_eq!(7, frames.matches("After step").count());
+        assert!(frames.trim_start().starts_with("After step 0:\n11111\n19991\n19191\n19991\n11111\n\nAfter step 1:\n34543\n40004\n"));
+        assert!(frames.ends_with("After step 6:\n00000\n00000\n00000\n00000\n00000\n\n"));
+
+        let frames = part2_frames(&generate("11\n11").unwrap());
+
+        assert_eq!(10, frames.matches("After step").count());
+        assert!(frames.ends_with("After step 9:\n00\n00\n\n"));
+    }
+
+    #[test]
+    fn step_records() {
+        let records: Vec<StepRecord> = history(&parse_input(include_str!("../input/2021/day11.part1.test.9.txt")).unwrap()).take(2).collect();
+
+        assert_eq!(9, records[0].flashes());
+        assert_eq!(2, records[0].waves);
+        assert_eq!(vec![(1, 1), (1, 2), (1, 3), (2, 1), (2, 3), (3, 1), (3, 2), (3, 3), (2, 2)], records[0].flashed);
+        assert_eq!(StepRecord { flashed: vec![], waves: 0 }, records[1]);
+    }
+
+    #[test]
+    fn periodic_after_synchronizing() {
+        let energy = parse_input(include_str!("../input/2021/day11.part2.test.195.txt")).unwrap();
+
+        assert_eq!((195, 10), cycle(&energy));
+        assert!(history(&energy).skip(194).step_by(10).take(5).all(|record| record.flashes() == 100));
+    }
+}
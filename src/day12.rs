@@ -1,4 +1,6 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use aoc_runner_derive::{aoc, aoc_generator};
 use std::num::ParseIntError;
 use itertools::Itertools;
@@ -22,53 +24,362 @@ fn parse(input: &str) -> Result<HashMap<String, Vec<String>>, ParseIntError> {
     )
 }
 
-fn num_paths(map: &&HashMap<String, Vec<String>>, is_eligible: fn(&Vec<&str>, &str) -> bool) -> usize {
-    let mut num_paths = 0;
-    let mut queue: VecDeque<Vec<&str>> = VecDeque::new();
-    queue.push_back(vec!["start"]);
+#[derive(Debug)]
+enum Day12Error {
+    UnknownCave(String),
+    InfinitePaths(String, String),
+    TooManySmallCaves(usize),
+}
+
+impl Display for Day12Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Day12Error::UnknownCave(name) => write!(f, "unknown cave: {}", name),
+            Day12Error::InfinitePaths(a, b) => write!(f, "infinitely many paths going back and forth between {} and {}", a, b),
+            Day12Error::TooManySmallCaves(n) => write!(f, "{} small caves, at most 64 are supported", n),
+        }
+    }
+}
 
-    while let Some(path) = queue.pop_front() {
-        let current = path.last().unwrap();
+impl Error for Day12Error {}
 
-        if current == &"end" {
-            num_paths += 1;
-            continue;
+struct CaveSystem {
+    names: Vec<String>,
+    neighbors: Vec<Vec<usize>>,
+    small: Vec<bool>,
+    masks: Vec<u64>,
+}
+
+impl CaveSystem {
+    /*
+     * Only small caves are ever marked as visited, so each of them gets a bit of its own and big caves
+     * can be as many as the input has.
+     */
+    fn new(map: &HashMap<String, Vec<String>>) -> Result<Self, Day12Error> {
+        let names: Vec<String> = map.keys().sorted().cloned().collect();
+        let index: HashMap<&String, usize> = names.iter().enumerate().map(|(i, name)| (name, i)).collect();
+        let small: Vec<bool> = names.iter().map(|name| &name.to_lowercase() == name).collect();
+        let num_small = small.iter().filter(|small| **small).count();
+
+        if num_small > 64 {
+            return Err(Day12Error::TooManySmallCaves(num_small));
         }
 
-        for neighbor in &map[&current.to_string()] {
-            if is_eligible(&path, neighbor) {
-                let mut next_path = path.clone();
-                next_path.push(neighbor);
-                queue.push_back(next_path);
-            }
+        let masks = small
+            .iter()
+            .scan(0, |bit, small| match small {
+                true => {
+                    *bit += 1;
+                    Some(1 << (*bit - 1))
+                },
+                false => Some(0),
+            })
+            .collect();
+
+        Ok(CaveSystem {
+            neighbors: names.iter().map(|name| map[name].iter().map(|neighbor| index[neighbor]).collect()).collect(),
+            small,
+            masks,
+            names,
+        })
+    }
+
+    fn index(&self, name: &str) -> Result<usize, Day12Error> {
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| Day12Error::UnknownCave(name.to_string()))
+    }
+
+    fn to_dot(&self, path: &[&str]) -> String {
+        let on_path = |cave: usize| path.contains(&self.names[cave].as_str());
+        let along_path = |a: usize, b: usize| path
+            .windows(2)
+            .any(|w| (w[0] == self.names[a] && w[1] == self.names[b]) || (w[0] == self.names[b] && w[1] == self.names[a]));
+
+        let nodes = (0..self.names.len())
+            .map(|cave| {
+                let shape = match self.small[cave] {
+                    true => "shape=circle",
+                    false => "shape=box, style=filled, fillcolor=lightgrey",
+                };
+
+                match on_path(cave) {
+                    true => format!("    \"{}\" [{}, color=red, penwidth=2];", self.names[cave], shape),
+                    false => format!("    \"{}\" [{}];", self.names[cave], shape),
+                }
+            });
+
+        let edges = (0..self.names.len())
+            .flat_map(|a| self.neighbors[a].iter().filter(move |b| a <= **b).map(move |b| (a, *b)))
+            .unique()
+            .map(|(a, b)| match along_path(a, b) {
+                true => format!("    \"{}\" -- \"{}\" [color=red, penwidth=2];", self.names[a], self.names[b]),
+                false => format!("    \"{}\" -- \"{}\";", self.names[a], self.names[b]),
+            });
+
+        format!("graph caves {{\n{}\n}}\n", nodes.chain(edges).join("\n"))
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Policy {
+    start: String,
+    end: String,
+    max_visits: usize,
+    revisitable: Vec<String>,
+    max_length: Option<usize>,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            start: "start".to_string(),
+            end: "end".to_string(),
+            max_visits: 1,
+            revisitable: vec![],
+            max_length: None,
         }
     }
+}
 
-    num_paths
+/*
+ * Big caves and small caves the policy allows revisiting can be entered any number of times. Every
+ * other small cave can be entered once, except for a single one of them which can be entered up to
+ * `max_visits` times. The start cave is never re-entered, and paths end as soon as they reach the end.
+ */
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+struct Visits {
+    visited: u64,
+    repeated: Option<(usize, usize)>,
+    length: usize,
 }
 
-#[aoc(day12, part1)]
-fn part1(map: &HashMap<String, Vec<String>>) -> usize {
-    num_paths(&map, |path, node| &node.to_uppercase() == node || !path.contains(&node))
+struct Traversal<'a> {
+    caves: &'a CaveSystem,
+    start: usize,
+    end: usize,
+    max_visits: usize,
+    limited: Vec<bool>,
+    max_length: Option<usize>,
+    dead_ends: HashSet<(usize, Visits)>,
 }
 
-#[aoc(day12, part2)]
-fn part2(map: &HashMap<String, Vec<String>>) -> usize {
-    num_paths(&map, |path, node| {
-        if node == "start" {
-            return false;
+impl<'a> Traversal<'a> {
+    fn new(caves: &'a CaveSystem, policy: &Policy) -> Result<Self, Day12Error> {
+        let revisitable: Vec<usize> = policy.revisitable.iter().map(|name| caves.index(name)).try_collect()?;
+
+        let mut traversal = Traversal {
+            caves,
+            start: caves.index(&policy.start)?,
+            end: caves.index(&policy.end)?,
+            max_visits: policy.max_visits,
+            limited: (0..caves.names.len()).map(|cave| caves.small[cave] && !revisitable.contains(&cave)).collect(),
+            max_length: policy.max_length,
+            dead_ends: HashSet::new(),
+        };
+
+        traversal.dead_ends = traversal.find_dead_ends()?;
+
+        Ok(traversal)
+    }
+
+    /*
+     * Going back and forth between two caves that can both be entered any number of times leaves the
+     * state of a traversal unchanged, so there are infinitely many paths exactly when a state can be
+     * revisited and the end can still be reached from it. Loops the end can't be reached from are
+     * harmless, as long as counting and enumerating paths don't enter them, so every state the end
+     * can't be reached from is remembered as a dead end. Paths limited in length can't loop forever,
+     * since the length is part of the state. A revisited state is reported along with the cave the loop
+     * came back to it from.
+     */
+    fn find_dead_ends(&self) -> Result<HashSet<(usize, Visits)>, Day12Error> {
+        if self.max_length.is_some() {
+            return Ok(HashSet::new());
+        }
+
+        let mut finished = HashMap::new();
+        self.reaches_end(&mut finished, &mut vec![], self.start, self.initial())?;
+
+        Ok(
+            finished
+                .into_iter()
+                .filter(|(_, reaches_end)| !reaches_end)
+                .map(|(state, _)| state)
+                .collect()
+        )
+    }
+
+    fn reaches_end(&self, finished: &mut HashMap<(usize, Visits), bool>, in_progress: &mut Vec<((usize, Visits), Option<usize>)>, current: usize, visits: Visits) -> Result<bool, Day12Error> {
+        if current == self.end {
+            return Ok(true);
+        }
+
+        if let Some(&reaches_end) = finished.get(&(current, visits)) {
+            return Ok(reaches_end);
         }
 
-        if &node.to_uppercase() == node || !path.contains(&node) {
-            return true;
+        let previous = in_progress.last().map(|((cave, _), _)| *cave);
+
+        if let Some(revisited) = in_progress.iter_mut().find(|(state, _)| *state == (current, visits)) {
+            revisited.1 = revisited.1.or(previous);
+            return Ok(false);
+        }
+
+        in_progress.push(((current, visits), None));
+
+        let mut reaches_end = false;
+        for &neighbor in &self.caves.neighbors[current] {
+            if let Some(next_visits) = self.enter(&visits, neighbor) {
+                reaches_end |= self.reaches_end(finished, in_progress, neighbor, next_visits)?;
+            }
         }
 
-        path
+        let (_, closing) = in_progress.pop().unwrap();
+
+        if let Some(closing) = closing.filter(|_| reaches_end) {
+            return Err(Day12Error::InfinitePaths(self.caves.names[current].clone(), self.caves.names[closing].clone()));
+        }
+
+        finished.insert((current, visits), reaches_end);
+
+        Ok(reaches_end)
+    }
+
+    fn initial(&self) -> Visits {
+        Visits { visited: self.caves.masks[self.start], repeated: None, length: 1 }
+    }
+
+    fn enter(&self, visits: &Visits, cave: usize) -> Option<Visits> {
+        if cave == self.start || self.max_length.is_some_and(|max_length| visits.length >= max_length) {
+            return None;
+        }
+
+        let length = match self.max_length {
+            Some(_) => visits.length + 1,
+            None => visits.length,
+        };
+
+        let mask = self.caves.masks[cave];
+        let next = match (self.limited[cave], visits.visited & mask == 0, visits.repeated) {
+            (false, _, _) => Visits { length, ..*visits },
+            (true, true, _) => Visits { visited: visits.visited | mask, length, ..*visits },
+            (true, false, None) if self.max_visits >= 2 => Visits { repeated: Some((cave, 2)), length, ..*visits },
+            (true, false, Some((repeated, n))) if repeated == cave && n < self.max_visits => Visits { repeated: Some((cave, n + 1)), length, ..*visits },
+            _ => return None,
+        };
+
+        match self.dead_ends.contains(&(cave, next)) {
+            true => None,
+            false => Some(next),
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.count_from(&mut HashMap::new(), self.start, self.initial())
+    }
+
+    fn count_from(&self, cache: &mut Cache, current: usize, visits: Visits) -> usize {
+        if current == self.end {
+            return 1;
+        }
+
+        if let Some(&cached_result) = cache.get(&(current, visits)) {
+            return cached_result;
+        }
+
+        let num_paths = self.caves.neighbors[current]
             .iter()
-            .filter(|n| n.to_lowercase() == **n)
-            .duplicates()
-            .count() == 0
-    })
+            .filter_map(|&neighbor| Some((neighbor, self.enter(&visits, neighbor)?)))
+            .map(|(neighbor, visits)| self.count_from(cache, neighbor, visits))
+            .sum();
+
+        cache.insert((current, visits), num_paths);
+
+        num_paths
+    }
+
+    fn paths(&self) -> Paths<'_> {
+        Paths { traversal: self, stack: vec![(self.start, self.initial(), 0)] }
+    }
+}
+
+type Cache = HashMap<(usize, Visits), usize>;
+
+struct Paths<'a> {
+    traversal: &'a Traversal<'a>,
+    stack: Vec<(usize, Visits, usize)>,
+}
+
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let traversal = self.traversal;
+
+        while let Some((current, visits, next_neighbor)) = self.stack.last_mut() {
+            let neighbors = &traversal.caves.neighbors[*current];
+
+            if *current == traversal.end {
+                let path = self.stack.iter().map(|(cave, _, _)| traversal.caves.names[*cave].as_str()).collect();
+                self.stack.pop();
+                return Some(path);
+            }
+
+            if *next_neighbor == neighbors.len() {
+                self.stack.pop();
+                continue;
+            }
+
+            let neighbor = neighbors[*next_neighbor];
+            *next_neighbor += 1;
+
+            if let Some(visits) = traversal.enter(visits, neighbor) {
+                self.stack.push((neighbor, visits, 0));
+            }
+        }
+
+        None
+    }
+}
+
+fn num_paths(map: &HashMap<String, Vec<String>>, policy: &Policy) -> Result<usize, Day12Error> {
+    let caves = CaveSystem::new(map)?;
+    Ok(Traversal::new(&caves, policy)?.count())
+}
+
+fn enumerate_paths(map: &HashMap<String, Vec<String>>, policy: &Policy) -> Result<usize, Day12Error> {
+    let caves = CaveSystem::new(map)?;
+    Ok(Traversal::new(&caves, policy)?.paths().count())
+}
+
+#[aoc(day12, part1)]
+fn part1(map: &HashMap<String, Vec<String>>) -> Result<usize, Day12Error> {
+    num_paths(map, &Policy::default())
+}
+
+#[aoc(day12, part1, enumerate)]
+fn part1_enumerate(map: &HashMap<String, Vec<String>>) -> Result<usize, Day12Error> {
+    enumerate_paths(map, &Policy::default())
+}
+
+#[aoc(day12, part1, dot)]
+fn part1_dot(map: &HashMap<String, Vec<String>>) -> Result<String, Day12Error> {
+    let caves = CaveSystem::new(map)?;
+    let traversal = Traversal::new(&caves, &Policy::default())?;
+    let longest = traversal.paths().max_by_key(|path| path.len()).unwrap_or_default();
+
+    Ok(format!("\n\n{}", caves.to_dot(&longest)))
+}
+
+#[aoc(day12, part2)]
+fn part2(map: &HashMap<String, Vec<String>>) -> Result<usize, Day12Error> {
+    num_paths(map, &Policy { max_visits: 2, ..Policy::default() })
+}
+
+#[aoc(day12, part2, enumerate)]
+fn part2_enumerate(map: &HashMap<String, Vec<String>>) -> Result<usize, Day12Error> {
+    enumerate_paths(map, &Policy { max_visits: 2, ..Policy::default() })
 }
 
 #[cfg(test)]
@@ -77,31 +388,178 @@ mod tests {
 
     #[test]
     fn part1_example1() {
-        assert_eq!(10, part1(&parse(include_str!("../input/2021/day12.part1.test.10.txt")).unwrap()));
+        assert_eq!(10, part1(&parse(include_str!("../input/2021/day12.part1.test.10.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part1_example2() {
-        assert_eq!(19, part1(&parse(include_str!("../input/2021/day12.part1.test.19.txt")).unwrap()));
+        assert_eq!(19, part1(&parse(include_str!("../input/2021/day12.part1.test.19.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part1_example3() {
-        assert_eq!(226, part1(&parse(include_str!("../input/2021/day12.part1.test.226.txt")).unwrap()));
+        assert_eq!(226, part1(&parse(include_str!("../input/2021/day12.part1.test.226.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part2_example1() {
-        assert_eq!(36, part2(&parse(include_str!("../input/2021/day12.part2.test.36.txt")).unwrap()));
+        assert_eq!(36, part2(&parse(include_str!("../input/2021/day12.part2.test.36.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part2_example2() {
-        assert_eq!(103, part2(&parse(include_str!("../input/2021/day12.part2.test.103.txt")).unwrap()));
+        assert_eq!(103, part2(&parse(include_str!("../input/2021/day12.part2.test.103.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part2_example3() {
-        assert_eq!(3509, part2(&parse(include_str!("../input/2021/day12.part2.test.3509.txt")).unwrap()));
+        assert_eq!(3509, part2(&parse(include_str!("../input/2021/day12.part2.test.3509.txt")).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn part1_many_small_caves() {
+        let input = "start-A\nA-end\n".to_string() + &"abcdefghij".chars().map(|c| format!("A-{}", c)).join("\n");
+        assert_eq!(9864101, part1(&parse(&input).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn many_big_caves() {
+        let input = (0..70).map(|i| format!("start-X{}\nX{}-end", i, i)).join("\n");
+        assert_eq!(70, part1(&parse(&input).unwrap()).unwrap());
+
+        let input = (0..65).map(|i| format!("start-x{}\nx{}-end", i, i)).join("\n");
+        assert!(matches!(num_paths(&parse(&input).unwrap(), &Policy::default()), Err(Day12Error::TooManySmallCaves(67))));
+    }
+
+    fn paths(map: &HashMap<String, Vec<String>>, policy: &Policy) -> Vec<String> {
+        let caves = CaveSystem::new(map).unwrap();
+        let traversal = Traversal::new(&caves, policy).unwrap();
+
+        traversal.paths().map(|path| path.join(",")).sorted().collect()
+    }
+
+    #[test]
+    fn paths_example1() {
+        let map = parse(include_str!("../input/2021/day12.part1.test.10.txt")).unwrap();
+        let expected = [
+            "start,A,b,A,c,A,end", "start,A,b,A,end", "start,A,b,end", "start,A,c,A,b,A,end", "start,A,c,A,b,end",
+            "start,A,c,A,end", "start,A,end", "start,b,A,c,A,end", "start,b,A,end", "start,b,end",
+        ];
+
+        assert_eq!(expected.to_vec(), paths(&map, &Policy::default()));
+        assert_eq!(36, paths(&map, &Policy { max_visits: 2, ..Policy::default() }).len());
+    }
+
+    #[test]
+    fn policies_count_enumerated_paths() {
+        let map = parse(include_str!("../input/2021/day12.part1.test.19.txt")).unwrap();
+        let policies = [
+            Policy { max_visits: 3, ..Policy::default() },
+            Policy { revisitable: vec!["dc".to_string()], max_length: Some(9), ..Policy::default() },
+            Policy { max_visits: 2, max_length: Some(6), ..Policy::default() },
+            Policy { start: "kj".to_string(), end: "LN".to_string(), ..Policy::default() },
+        ];
+
+        for policy in policies {
+            let paths = paths(&map, &policy);
+
+            assert_eq!(num_paths(&map, &policy).unwrap(), paths.len(), "{:?}", policy);
+            assert!(paths.iter().all(|path| path.split(',').count() <= policy.max_length.unwrap_or(usize::MAX)));
+            assert!(paths.iter().all(|path| path.starts_with(&policy.start) && path.ends_with(&policy.end)));
+        }
+    }
+
+    #[test]
+    fn revisitable_small_cave() {
+        let map = parse(include_str!("../input/2021/day12.part1.test.19.txt")).unwrap();
+        let paths = paths(&map, &Policy { revisitable: vec!["dc".to_string()], max_length: Some(9), ..Policy::default() });
+
+        assert!(paths.iter().any(|path| path.split(',').filter(|cave| *cave == "dc").count() > 2));
+    }
+
+    #[test]
+    fn start_is_end() {
+        let map = parse(include_str!("../input/2021/day12.part1.test.10.txt")).unwrap();
+        let policy = Policy { end: "start".to_string(), ..Policy::default() };
+
+        assert_eq!(vec!["start"], paths(&map, &policy));
+        assert_eq!(1, num_paths(&map, &policy).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn unknown_cave() {
+        let map = parse(include_str!("../input/2021/day12.part1.test.10.txt")).unwrap();
+        assert!(matches!(num_paths(&map, &Policy { end: "exit".to_string(), ..Policy::default() }), Err(Day12Error::UnknownCave(_))));
+    }
+
+    #[test]
+    fn paths_are_enumerated_lazily() {
+        let input = "start-A\nA-end\n".to_string() + &"abcdefghij".chars().map(|c| format!("A-{}", c)).join("\n");
+        let map = parse(&input).unwrap();
+        let caves = CaveSystem::new(&map).unwrap();
+        let traversal = Traversal::new(&caves, &Policy::default()).unwrap();
+
+        assert_eq!(5, traversal.paths().take(5).count());
+    }
+
+    #[test]
+    fn connected_big_caves() {
+        let map = parse("start-A\nA-B\nB-end").unwrap();
+
+        assert!(matches!(num_paths(&map, &Policy::default()), Err(Day12Error::InfinitePaths(a, b)) if a == "A" && b == "B"));
+        assert_eq!(1, num_paths(&map, &Policy { max_length: Some(4), ..Policy::default() }).unwrap());
+        assert_eq!(2, num_paths(&map, &Policy { max_length: Some(6), ..Policy::default() }).unwrap());
+    }
+
+    #[test]
+    fn connected_big_caves_off_any_path() {
+        assert_eq!(1, part1(&parse("start-a\na-end\nB-C\nC-a").unwrap()).unwrap());
+        assert_eq!(1, part1_enumerate(&parse("start-a\na-end\nB-C\nC-a").unwrap()).unwrap());
+        assert_eq!(1, part1(&parse("start-a\na-end\nB-C").unwrap()).unwrap());
+    }
+
+    #[test]
+    fn revisitable_cave_next_to_big_cave() {
+        let map = parse(include_str!("../input/2021/day12.part1.test.10.txt")).unwrap();
+        let policy = Policy { revisitable: vec!["c".to_string()], ..Policy::default() };
+
+        assert!(matches!(num_paths(&map, &policy), Err(Day12Error::InfinitePaths(a, b)) if a == "A" && b == "c"));
+    }
+
+    #[test]
+    fn runners_return_errors() {
+        let map = parse("start-A\nA-B\nB-end").unwrap();
+
+        assert!(matches!(part1(&map), Err(Day12Error::InfinitePaths(_, _))));
+        assert!(matches!(part1_enumerate(&map), Err(Day12Error::InfinitePaths(_, _))));
+        assert!(matches!(part1_dot(&map), Err(Day12Error::InfinitePaths(_, _))));
+        assert!(matches!(part2(&map), Err(Day12Error::InfinitePaths(_, _))));
+        assert!(matches!(part2_enumerate(&map), Err(Day12Error::InfinitePaths(_, _))));
+    }
+
+    #[test]
+    fn loop_after_small_cave() {
+        let map = parse("start-A\nA-b\nb-C\nC-end\nA-C").unwrap();
+        assert!(matches!(num_paths(&map, &Policy::default()), Err(Day12Error::InfinitePaths(a, b)) if a == "A" && b == "C"));
+    }
+
+    #[test]
+    fn dot_export() {
+        let caves = CaveSystem::new(&parse("start-A\nA-b\nA-end\nb-end").unwrap()).unwrap();
+        let expected = [
+            "graph caves {",
+            "    \"A\" [shape=box, style=filled, fillcolor=lightgrey, color=red, penwidth=2];",
+            "    \"b\" [shape=circle, color=red, penwidth=2];",
+            "    \"end\" [shape=circle, color=red, penwidth=2];",
+            "    \"start\" [shape=circle, color=red, penwidth=2];",
+            "    \"A\" -- \"start\" [color=red, penwidth=2];",
+            "    \"A\" -- \"b\" [color=red, penwidth=2];",
+            "    \"A\" -- \"end\";",
+            "    \"b\" -- \"end\" [color=red, penwidth=2];",
+            "}",
+            "",
+        ].join("\n");
+
+        assert_eq!(expected, caves.to_dot(&["start", "A", "b", "end"]));
+    }
+}
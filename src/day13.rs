@@ -1,55 +1,181 @@
 use std::cmp::max;
 use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use aoc_runner_derive::{aoc, aoc_generator};
+use itertools::Itertools;
 use regex::Regex;
+use crate::netpbm;
 
-#[aoc_generator(day13)]
-fn parse(input: &str) -> (HashSet<(i32, i32)>, Vec<(String, i32)>) {
-    let coords: HashSet<(i32, i32)> = input
-        .lines()
-        .filter(|line| line.contains(","))
-        .map(|line| {
-            let mut parts = line.split(",");
-            (parts.next().unwrap().parse().unwrap(), parts.next().unwrap().parse().unwrap())
-        })
-        .collect();
+#[derive(Debug)]
+enum Day13Error {
+    InvalidDot(usize, String),
+    InvalidFold(usize, String),
+    FoldOutsidePaper(Fold, i32),
+    FoldPastEdge(Fold, i32),
+    DotOnFoldLine(Fold, (i32, i32)),
+}
 
-    let re = Regex::new(r"^fold along ([xy])=(\d+)$").unwrap();
-    let folds: Vec<(String, i32)> = input.lines()
-        .filter_map(|line| {
-            match re.captures(line) {
-                None => None,
-                Some(c) => {
-
-                    Some((c.get(1).unwrap().as_str().to_string(), c.get(2).unwrap().as_str().parse().unwrap()))
-                }
-            }
-        })
-        .collect();
+impl Display for Day13Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Day13Error::InvalidDot(line, dot) => write!(f, "line {}: invalid dot: {}", line, dot),
+            Day13Error::InvalidFold(line, fold) => write!(f, "line {}: invalid fold: {}", line, fold),
+            Day13Error::FoldOutsidePaper(fold, extent) => write!(f, "{} lies outside paper of size {}", fold, extent),
+            Day13Error::FoldPastEdge(fold, extent) => write!(f, "{} folds past the edge of paper of size {}", fold, extent),
+            Day13Error::DotOnFoldLine(fold, (x, y)) => write!(f, "{} runs through dot {},{}", fold, x, y),
+        }
+    }
+}
+
+impl Error for Day13Error {}
 
-    (coords, folds)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Fold {
+    AlongX(i32),
+    AlongY(i32),
 }
 
-fn fold(coords: &HashSet<(i32, i32)>, fold_axis: &String, fold_position: &i32) -> HashSet<(i32, i32)> {
-    coords.into_iter()
-        .map(|(x, y)| match fold_axis.as_str() {
-            "x" => (*x, *y),
-            "y" => (*y, *x),
-            _ => panic!(),
-        })
-        .map(|(mut x, y)| {
-            x = x - fold_position;
-            x = x.abs();
-            x = fold_position - x;
+impl Display for Fold {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Fold::AlongX(x) => write!(f, "fold along x={}", x),
+            Fold::AlongY(y) => write!(f, "fold along y={}", y),
+        }
+    }
+}
 
-            (x, y)
-        })
-        .map(|(x, y)| match fold_axis.as_str() {
-            "x" => (x, y),
-            "y" => (y, x),
-            _ => panic!(),
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Paper {
+    dots: HashSet<(i32, i32)>,
+    width: i32,
+    height: i32,
+}
+
+impl Paper {
+    fn new(dots: HashSet<(i32, i32)>) -> Self {
+        let (width, height) = dots.iter()
+            .fold((0, 0), |(w, h), (x, y)| (
+                max(w, x + 1),
+                max(h, y + 1),
+            ));
+
+        Paper { dots, width, height }
+    }
+
+    /*
+     * The dots don't necessarily reach the edges of the paper, but the first fold along each axis is
+     * always through the middle of it, which gives its actual size.
+     */
+    fn with_folds(dots: HashSet<(i32, i32)>, folds: &[Fold]) -> Self {
+        let paper = Paper::new(dots);
+        let width = folds.iter().find_map(|fold| match fold {
+            Fold::AlongX(x) => Some(2 * x + 1),
+            Fold::AlongY(_) => None,
+        });
+        let height = folds.iter().find_map(|fold| match fold {
+            Fold::AlongX(_) => None,
+            Fold::AlongY(y) => Some(2 * y + 1),
+        });
+
+        Paper {
+            width: max(paper.width, width.unwrap_or(0)),
+            height: max(paper.height, height.unwrap_or(0)),
+            ..paper
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.dots.len()
+    }
+
+    /*
+     * The paper is folded so that the far side is reflected onto the near side: a dot at distance d
+     * beyond the fold line ends up at distance d before it. That leaves the near side as the new
+     * paper, so the far side mustn't be longer than it, and nothing may sit on the line itself.
+     */
+    fn fold(&self, fold: Fold) -> Result<Paper, Day13Error> {
+        let (position, extent) = match fold {
+            Fold::AlongX(x) => (x, self.width),
+            Fold::AlongY(y) => (y, self.height),
+        };
+
+        if position >= extent {
+            return Err(Day13Error::FoldOutsidePaper(fold, extent));
+        }
+
+        if extent > 2 * position + 1 {
+            return Err(Day13Error::FoldPastEdge(fold, extent));
+        }
+
+        let reflect = |v: i32| match v > position {
+            true => 2 * position - v,
+            false => v,
+        };
+
+        let dots = self.dots
+            .iter()
+            .map(|&(x, y)| match fold {
+                Fold::AlongX(_) if x == position => Err(Day13Error::DotOnFoldLine(fold, (x, y))),
+                Fold::AlongY(_) if y == position => Err(Day13Error::DotOnFoldLine(fold, (x, y))),
+                Fold::AlongX(_) => Ok((reflect(x), y)),
+                Fold::AlongY(_) => Ok((x, reflect(y))),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(match fold {
+            Fold::AlongX(_) => Paper { dots, width: position, height: self.height },
+            Fold::AlongY(_) => Paper { dots, width: self.width, height: position },
         })
-        .collect()
+    }
+
+    fn fold_all(&self, folds: &[Fold]) -> Result<Vec<Paper>, Day13Error> {
+        let mut papers: Vec<Paper> = vec![];
+
+        for fold in folds {
+            let paper = papers.last().unwrap_or(self).fold(*fold)?;
+            papers.push(paper);
+        }
+
+        Ok(papers)
+    }
+}
+
+#[aoc_generator(day13)]
+fn parse(input: &str) -> Result<(Paper, Vec<Fold>), Day13Error> {
+    let re = Regex::new(r"^fold along ([xy])=(\d+)$").unwrap();
+    let mut dots = HashSet::new();
+    let mut folds = vec![];
+
+    for (i, line) in input.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.starts_with("fold") {
+            let fold = re.captures(line)
+                .and_then(|c| {
+                    let position = c[2].parse().ok()?;
+
+                    match &c[1] {
+                        "x" => Some(Fold::AlongX(position)),
+                        _ => Some(Fold::AlongY(position)),
+                    }
+                })
+                .ok_or_else(|| Day13Error::InvalidFold(i, line.to_string()))?;
+
+            folds.push(fold);
+        } else {
+            let dot = line.split_once(',')
+                .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)))
+                .filter(|&(x, y): &(i32, i32)| x >= 0 && y >= 0)
+                .ok_or_else(|| Day13Error::InvalidDot(i, line.to_string()))?;
+
+            dots.insert(dot);
+        }
+    }
+
+    Ok((Paper::with_folds(dots, &folds), folds))
 }
 
 fn print_coords(coords: &HashSet<(i32, i32)>) -> String {
@@ -75,26 +201,149 @@ fn print_coords(coords: &HashSet<(i32, i32)>) -> String {
     String::from_iter(chars)
 }
 
-#[aoc(day13, part1)]
-fn part1((coords, folds): &(HashSet<(i32, i32)>, Vec<(String, i32)>)) -> usize {
-    let mut coords = coords.clone();
+/*
+ * The letters are drawn in one of two fixed-size fonts, 4x6 or 6x10 dots, with every letter taking up the
+ * same number of columns including the gap before the next one. Not every capital letter has been seen
+ * in either font, so only the known ones are included.
+ */
+const SMALL_FONT: (usize, usize, usize, &[(char, &str)]) = (6, 4, 5, &[
+    ('A', ".##.#..##..######..##..#"),
+    ('B', "###.#..####.#..##..####."),
+    ('C', ".##.#..##...#...#..#.##."),
+    ('E', "#####...###.#...#...####"),
+    ('F', "#####...###.#...#...#..."),
+    ('G', ".##.#..##...#.###..#.###"),
+    ('H', "#..##..######..##..##..#"),
+    ('I', ".###..#...#...#...#..###"),
+    ('J', "..##...#...#...##..#.##."),
+    ('K', "#..##.#.##..#.#.#.#.#..#"),
+    ('L', "#...#...#...#...#...####"),
+    ('O', ".##.#..##..##..##..#.##."),
+    ('P', "###.#..##..####.#...#..."),
+    ('R', "###.#..##..####.#.#.#..#"),
+    ('S', ".####...#....##....####."),
+    ('U', "#..##..##..##..##..#.##."),
+    ('Z', "####...#..#..#..#...####"),
+]);
+
+const LARGE_FONT: (usize, usize, usize, &[(char, &str)]) = (10, 6, 8, &[
+    ('A', "..##...#..#.#....##....##....########....##....##....##....#"),
+    ('B', "#####.#....##....##....######.#....##....##....##....######."),
+    ('C', ".####.#....##.....#.....#.....#.....#.....#.....#....#.####."),
+    ('E', "#######.....#.....#.....#####.#.....#.....#.....#.....######"),
+    ('F', "#######.....#.....#.....#####.#.....#.....#.....#.....#....."),
+    ('G', ".####.#....##.....#.....#.....#..####....##....##...##.###.#"),
+    ('H', "#....##....##....##....########....##....##....##....##....#"),
+    ('J', "...###....#.....#.....#.....#.....#.....#.#...#.#...#..###.."),
+    ('K', "#....##...#.#..#..#.#...##....##....#.#...#..#..#...#.#....#"),
+    ('L', "#.....#.....#.....#.....#.....#.....#.....#.....#.....######"),
+    ('N', "#....###...###...##.#..##.#..##..#.##..#.##...###...###....#"),
+    ('P', "#####.#....##....##....######.#.....#.....#.....#.....#....."),
+    ('R', "#####.#....##....##....######.#..#..#...#.#...#.#....##....#"),
+    ('X', "#....##....#.#..#..#..#...##....##...#..#..#..#.#....##....#"),
+    ('Z', "######.....#.....#....#....#....#....#....#.....#.....######"),
+]);
+
+#[derive(Debug)]
+enum OcrError {
+    UnsupportedHeight(i32),
+    UnrecognizedGlyphs(String, Vec<usize>),
+}
 
-    if let Some((axis, fold_position)) = folds.first() {
-        coords = fold(&coords, axis, fold_position);
+impl Display for OcrError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            OcrError::UnsupportedHeight(height) => write!(f, "no font is {} dots high", height),
+            OcrError::UnrecognizedGlyphs(text, positions) => write!(f, "unrecognized glyphs at {:?} in {}", positions, text),
+        }
     }
+}
+
+fn recognize(coords: &HashSet<(i32, i32)>) -> Result<String, OcrError> {
+    let (height, width) = coords.iter()
+        .fold((0, 0), |(h, w), (x, y)| (
+            max(h, y + 1),
+            max(w, x + 1),
+        ));
+
+    let (glyph_height, glyph_width, stride, glyphs) = match height {
+        6 => SMALL_FONT,
+        10 => LARGE_FONT,
+        _ => return Err(OcrError::UnsupportedHeight(height)),
+    };
 
-    coords.len()
+    let num_glyphs = (width as usize).div_ceil(stride);
+    let text: String = (0..num_glyphs)
+        .map(|k| {
+            let glyph: String = (0..glyph_height)
+                .flat_map(|y| (0..glyph_width).map(move |x| (k * stride + x, y)))
+                .map(|(x, y)| match coords.contains(&(x as i32, y as i32)) {
+                    true => '#',
+                    false => '.',
+                })
+                .collect();
+
+            glyphs
+                .iter()
+                .find(|(_, known)| *known == glyph)
+                .map_or('?', |(letter, _)| *letter)
+        })
+        .collect();
+
+    let unrecognized: Vec<usize> = text.chars().positions(|c| c == '?').collect();
+
+    match unrecognized.is_empty() {
+        true => Ok(text),
+        false => Err(OcrError::UnrecognizedGlyphs(text, unrecognized)),
+    }
+}
+
+#[aoc(day13, part1)]
+fn part1((paper, folds): &(Paper, Vec<Fold>)) -> Result<usize, Day13Error> {
+    match folds.first() {
+        Some(fold) => Ok(paper.fold(*fold)?.len()),
+        None => Ok(paper.len()),
+    }
+}
+
+#[aoc(day13, part1, counts)]
+fn part1_counts((paper, folds): &(Paper, Vec<Fold>)) -> Result<String, Day13Error> {
+    let papers = paper.fold_all(folds)?;
+
+    Ok(papers
+        .iter()
+        .zip(folds)
+        .map(|(paper, fold)| format!("{}: {}", fold, paper.len()))
+        .join(", "))
+}
+
+fn folded((paper, folds): &(Paper, Vec<Fold>)) -> Result<Paper, Day13Error> {
+    Ok(paper.fold_all(folds)?
+        .pop()
+        .unwrap_or_else(|| paper.clone()))
 }
 
+/*
+ * Papers that don't fold into letters of a known font are printed as they are instead.
+ */
 #[aoc(day13, part2)]
-fn part2((coords, folds): &(HashSet<(i32, i32)>, Vec<(String, i32)>)) -> String {
-    let mut coords = coords.clone();
+fn part2(input: &(Paper, Vec<Fold>)) -> Result<String, Day13Error> {
+    let paper = folded(input)?;
+    Ok(recognize(&paper.dots).unwrap_or_else(|_| format!("\n\n{}", print_coords(&paper.dots))))
+}
 
-    for (axis, fold_position) in folds {
-        coords = fold(&coords, axis, fold_position);
-    }
+#[aoc(day13, part2, print)]
+fn part2_print(input: &(Paper, Vec<Fold>)) -> Result<String, Day13Error> {
+    Ok(format!("\n\n{}", print_coords(&folded(input)?.dots)))
+}
+
+fn to_pbm(paper: &Paper) -> String {
+    netpbm::pbm(paper.height as usize, paper.width as usize, |(y, x)| paper.dots.contains(&(x as i32, y as i32)))
+}
 
-    format!("\n\n{}", print_coords(&coords))
+#[aoc(day13, part2, pbm)]
+fn part2_pbm(input: &(Paper, Vec<Fold>)) -> Result<String, Box<dyn Error>> {
+    Ok(netpbm::save("day13.pbm", &to_pbm(&folded(input)?))?)
 }
 
 #[cfg(test)]
@@ -103,11 +352,120 @@ mod tests {
 
     #[test]
     fn part1_example() {
-        assert_eq!(17, part1(&parse(include_str!("../input/2021/day13.part1.test.17.txt"))));
+        assert_eq!(17, part1(&parse(include_str!("../input/2021/day13.part1.test.17.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part2_example() {
-        assert_eq!(16, part2(&parse(include_str!("../input/2021/day13.part2.test.16.txt"))).chars().filter(|c| *c == '▓').count());
+        assert_eq!(16, part2(&parse(include_str!("../input/2021/day13.part2.test.16.txt")).unwrap()).unwrap().chars().filter(|c| *c == '▓').count());
+    }
+
+    fn render(glyphs: &[&str], height: usize, width: usize, stride: usize) -> HashSet<(i32, i32)> {
+        glyphs
+            .iter()
+            .enumerate()
+            .flat_map(|(k, glyph)| glyph.chars().enumerate().filter(|(_, c)| *c == '#').map(move |(i, _)| (k * stride + i % width, i / width)))
+            .map(|(x, y)| (x as i32, y as i32))
+            .filter(|(_, y)| (*y as usize) < height)
+            .collect()
+    }
+
+    #[test]
+    fn recognize_small_font() {
+        let (height, width, stride, glyphs) = SMALL_FONT;
+        let letters: String = glyphs.iter().map(|(letter, _)| *letter).collect();
+        let coords = render(&glyphs.iter().map(|(_, glyph)| *glyph).collect::<Vec<_>>(), height, width, stride);
+
+        assert_eq!(letters, recognize(&coords).unwrap());
+    }
+
+    #[test]
+    fn recognize_large_font() {
+        let (height, width, stride, glyphs) = LARGE_FONT;
+        let letters: String = glyphs.iter().map(|(letter, _)| *letter).collect();
+        let coords = render(&glyphs.iter().map(|(_, glyph)| *glyph).collect::<Vec<_>>(), height, width, stride);
+
+        assert_eq!(letters, recognize(&coords).unwrap());
+    }
+
+    #[test]
+    fn recognize_unknown_glyphs() {
+        let (height, width, stride, _) = SMALL_FONT;
+        let coords = render(&["#..##..######..##..##..#", "########################", "#...#...#...#...#...####", "#.#.#.#.#.#.#.#.#.#.#.#."], height, width, stride);
+
+        match recognize(&coords) {
+            Err(OcrError::UnrecognizedGlyphs(text, positions)) => {
+                assert_eq!("H?L?", text);
+                assert_eq!(vec![1, 3], positions);
+            },
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn recognize_unsupported_height() {
+        let paper = folded(&parse(include_str!("../input/2021/day13.part2.test.16.txt")).unwrap()).unwrap();
+        assert!(matches!(recognize(&paper.dots), Err(OcrError::UnsupportedHeight(5))));
+    }
+
+    #[test]
+    fn dot_counts_after_each_fold() {
+        let input = parse(include_str!("../input/2021/day13.part1.test.17.txt")).unwrap();
+
+        assert_eq!("fold along y=7: 17, fold along x=5: 16", part1_counts(&input).unwrap());
+        assert_eq!((5, 7), {
+            let paper = folded(&input).unwrap();
+            (paper.width, paper.height)
+        });
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(parse("1,2\n3;4\n"), Err(Day13Error::InvalidDot(2, _))));
+        assert!(matches!(parse("1,2\n-1,4\n"), Err(Day13Error::InvalidDot(2, _))));
+        assert!(matches!(parse("1,2\n\nfold along x=1\nfold along z=3\n"), Err(Day13Error::InvalidFold(4, _))));
+    }
+
+    #[test]
+    fn invalid_folds() {
+        let (paper, _) = parse("0,0\n4,2\n2,1\n").unwrap();
+
+        assert!(matches!(paper.fold(Fold::AlongX(2)), Err(Day13Error::DotOnFoldLine(Fold::AlongX(2), (2, 1)))));
+        assert!(matches!(paper.fold(Fold::AlongY(3)), Err(Day13Error::FoldOutsidePaper(Fold::AlongY(3), 3))));
+        assert!(matches!(paper.fold(Fold::AlongX(1)), Err(Day13Error::FoldPastEdge(Fold::AlongX(1), 5))));
+
+        let paper = Paper::new(HashSet::from([(0, 0), (4, 2), (1, 0)]));
+        assert_eq!(HashSet::from([(0, 0), (4, 0), (1, 0)]), paper.fold(Fold::AlongY(1)).unwrap().dots);
+    }
+
+    #[test]
+    fn runners_return_fold_errors() {
+        let input = parse("0,0\n4,2\n2,1\n\nfold along x=2\n").unwrap();
+
+        assert!(matches!(part1(&input), Err(Day13Error::DotOnFoldLine(Fold::AlongX(2), (2, 1)))));
+        assert!(matches!(part1_counts(&input), Err(Day13Error::DotOnFoldLine(_, _))));
+        assert!(matches!(part2(&input), Err(Day13Error::DotOnFoldLine(_, _))));
+        assert!(matches!(part2_print(&input), Err(Day13Error::DotOnFoldLine(_, _))));
+
+        let input = parse("0,0\n4,2\n\nfold along x=1\n").unwrap();
+        assert!(matches!(part1(&input), Err(Day13Error::FoldPastEdge(Fold::AlongX(1), 5))));
+    }
+
+    #[test]
+    fn folds_beyond_last_dot() {
+        let input = parse("0,0\n1,3\n\nfold along x=5\nfold along y=4\nfold along x=2\n").unwrap();
+        let paper = folded(&input).unwrap();
+
+        assert_eq!((11, 9), (input.0.width, input.0.height));
+        assert_eq!((2, 4), (paper.width, paper.height));
+        assert_eq!(HashSet::from([(0, 0), (1, 3)]), paper.dots);
+    }
+
+    #[test]
+    fn export_pbm() {
+        let paper = folded(&parse(include_str!("../input/2021/day13.part2.test.16.txt")).unwrap()).unwrap();
+
+        assert_eq!((5, 7), (paper.width, paper.height));
+        assert_eq!("P1\n5 7\n11111100011000110001111110000000000\n", to_pbm(&paper));
     }
-}
\ No newline at end of file
+}
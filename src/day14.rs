@@ -1,80 +1,322 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use aoc_runner_derive::{aoc, aoc_generator};
 use regex::Regex;
 use itertools::Itertools;
 
-#[aoc_generator(day14)]
-fn parse(input: &str) -> (Vec<char>, HashMap<(char, char), char>) {
-    let template = input.lines().next().unwrap().chars().collect();
-
-    let re = Regex::new(r"^(.)(.) -> (.)$").unwrap();
-    let rules = input
-        .lines()
-        .skip(2)
-        .map(|line| {
-            let caps = re.captures(line).unwrap();
-            (
-                (
-                    caps.get(1).unwrap().as_str().chars().next().unwrap(),
-                    caps.get(2).unwrap().as_str().chars().next().unwrap(),
-                ),
-                caps.get(3).unwrap().as_str().chars().next().unwrap()
-            )
-        })
-        .collect();
+type Rules = HashMap<(char, char), char>;
 
-    (template, rules)
+#[derive(Debug)]
+enum Day14Error {
+    MissingTemplate,
+    InvalidRule(usize, String),
+    DuplicateRule(usize, (char, char)),
+    ConflictingRule(usize, (char, char), char, char),
+    Overflow(u64),
+    ZeroModulus,
 }
 
-#[aoc(day14, part1)]
-fn part1((template, rules): &(Vec<char>, HashMap<(char, char), char>)) -> usize {
-    let mut polymer = template.clone();
+impl Display for Day14Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Day14Error::MissingTemplate => write!(f, "missing polymer template"),
+            Day14Error::InvalidRule(line, rule) => write!(f, "line {}: invalid rule: {}", line, rule),
+            Day14Error::DuplicateRule(line, (a, b)) => write!(f, "line {}: duplicate rule for {}{}", line, a, b),
+            Day14Error::ConflictingRule(line, (a, b), first, second) => write!(f, "line {}: {}{} inserts {}, but an earlier rule inserts {}", line, a, b, second, first),
+            Day14Error::Overflow(steps) => write!(f, "counts after {} steps overflow, use a modulus", steps),
+            Day14Error::ZeroModulus => write!(f, "counts can't be taken modulo 0"),
+        }
+    }
+}
+
+impl Error for Day14Error {}
+
+#[aoc_generator(day14)]
+fn parse(input: &str) -> Result<(Vec<char>, Rules), Day14Error> {
+    let mut lines = input.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+    let template: Vec<char> = lines
+        .next()
+        .map(|(_, line)| line.chars().collect())
+        .filter(|template: &Vec<char>| !template.is_empty())
+        .ok_or(Day14Error::MissingTemplate)?;
 
-    for _step in 0..10 {
-        let between: Vec<char> = polymer
+    let re = Regex::new(r"^(\w)(\w) -> (\w)$").unwrap();
+    let mut rules = Rules::new();
+
+    for (i, line) in lines.filter(|(_, line)| !line.is_empty()) {
+        let (a, b, c) = re.captures(line)
+            .and_then(|caps| (1..=3).filter_map(|k| caps[k].chars().next()).collect_tuple())
+            .ok_or_else(|| Day14Error::InvalidRule(i, line.to_string()))?;
+
+        match rules.insert((a, b), c) {
+            Some(previous) if previous == c => return Err(Day14Error::DuplicateRule(i, (a, b))),
+            Some(previous) => return Err(Day14Error::ConflictingRule(i, (a, b), previous, c)),
+            None => (),
+        }
+    }
+
+    Ok((template, rules))
+}
+
+fn polymer(template: &[char], rules: &Rules, steps: usize) -> Vec<char> {
+    (0..steps).fold(template.to_vec(), |polymer, _step| {
+        polymer
             .windows(2)
-            .filter_map(|w| rules.get(&(w[0], w[1])))
-            .cloned()
-            .collect();
+            .flat_map(|w| [Some(w[0]), rules.get(&(w[0], w[1])).copied()])
+            .flatten()
+            .chain(polymer.last().copied())
+            .collect()
+    })
+}
 
-        polymer = polymer.into_iter().interleave(between).collect();
+/*
+ * A pair without a rule simply has nothing inserted between its elements, but that is more often a gap in the
+ * rules than intended, so those that can ever occur in the polymer can be listed. The pairs that can occur are
+ * the template's pairs and, for each of them with a rule, the two pairs either side of its inserted element.
+ */
+fn missing_rules(template: &[char], rules: &Rules) -> Vec<(char, char)> {
+    let mut seen: HashSet<(char, char)> = HashSet::new();
+    let mut pending: Vec<(char, char)> = template.windows(2).map(|w| (w[0], w[1])).collect();
+    let mut missing = vec![];
+
+    while let Some((a, b)) = pending.pop() {
+        if !seen.insert((a, b)) {
+            continue;
+        }
+
+        match rules.get(&(a, b)) {
+            Some(c) => pending.extend([(a, *c), (*c, b)]),
+            None => missing.push((a, b)),
+        }
     }
 
+    missing.sort();
+    missing
+}
+
+#[aoc(day14, part1)]
+fn part1((template, rules): &(Vec<char>, Rules)) -> usize {
+    let polymer = polymer(template, rules, 10);
     let counts = polymer.iter().counts();
     let ((_, min), (_, max)) = counts.iter().minmax_by_key(|(_, count)| **count).into_option().unwrap();
 
     max - min
 }
 
-#[aoc(day14, part2)]
-fn part2((template, rules): &(Vec<char>, HashMap<(char, char), char>)) -> usize {
-    let mut pairs = template
-        .windows(2)
-        .map(|w| (w[0], w[1]))
-        .counts();
-
-    for _step in 0..40 {
-        for ((a, b), count) in pairs.clone().into_iter() {
-            if let Some(rule) = rules.get(&(a, b)) {
-                *pairs.entry((a, b)).or_insert(0) -= count;
-                *pairs.entry((a, *rule)).or_insert(0) += count;
-                *pairs.entry((*rule, b)).or_insert(0) += count;
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Histogram {
+    elements: HashMap<char, u128>,
+    pairs: HashMap<(char, char), u128>,
+}
+
+impl Histogram {
+    fn new(template: &[char]) -> Self {
+        let pairs = template
+            .windows(2)
+            .map(|w| (w[0], w[1]))
+            .counts()
+            .into_iter()
+            .map(|(pair, count)| (pair, count as u128))
+            .collect();
+
+        let elements = template
+            .iter()
+            .counts()
+            .into_iter()
+            .map(|(element, count)| (*element, count as u128))
+            .collect();
+
+        Histogram { elements, pairs }
+    }
+
+    /*
+     * The histogram after one more step, or `None` once the counts no longer fit.
+     */
+    fn step(&self, rules: &Rules) -> Option<Histogram> {
+        let mut next = Histogram { elements: self.elements.clone(), pairs: HashMap::new() };
+
+        for (&(a, b), &count) in &self.pairs {
+            let inserted = match rules.get(&(a, b)) {
+                Some(c) => *c,
+                None => {
+                    *next.pairs.entry((a, b)).or_default() += count;
+                    continue;
+                },
+            };
+
+            for pair in [(a, inserted), (inserted, b)] {
+                let total = next.pairs.entry(pair).or_default();
+                *total = total.checked_add(count)?;
+            }
+
+            let total = next.elements.entry(inserted).or_default();
+            *total = total.checked_add(count)?;
+        }
+
+        Some(next)
+    }
+}
+
+/*
+ * Histograms after every step, starting with the template itself, for as long as the counts fit in u128.
+ */
+fn histograms<'a>(template: &[char], rules: &'a Rules) -> impl Iterator<Item = Histogram> + 'a {
+    std::iter::successors(Some(Histogram::new(template)), |histogram| histogram.step(rules))
+}
+
+/*
+ * Every step maps each pair to the two pairs either side of its inserted element, independently of the rest of
+ * the polymer, so a step is a linear map on pair counts and n steps are the n-th power of its matrix. Exact
+ * counts roughly double every step and outgrow even u128 after a hundred or so, so beyond that the counts can
+ * only be given modulo some number, which must fit in 64 bits so that products of two residues can't overflow.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Matrix {
+    size: usize,
+    cells: Vec<u128>,
+}
+
+impl Matrix {
+    fn identity(size: usize) -> Self {
+        Matrix { size, cells: (0..size * size).map(|k| (k / size == k % size) as u128).collect() }
+    }
+
+    fn mul(&self, other: &Matrix, modulus: Option<u64>) -> Option<Matrix> {
+        let mut cells = vec![0; self.size * self.size];
+
+        for (i, j) in (0..self.size).cartesian_product(0..self.size) {
+            let mut sum: u128 = 0;
+
+            for k in 0..self.size {
+                let product = self.cells[i * self.size + k].checked_mul(other.cells[k * self.size + j])?;
+
+                sum = match modulus {
+                    Some(m) => (sum + product % m as u128) % m as u128,
+                    None => sum.checked_add(product)?,
+                };
+            }
+
+            cells[i * self.size + j] = sum;
+        }
+
+        Some(Matrix { size: self.size, cells })
+    }
+
+    fn pow(&self, mut exponent: u64, modulus: Option<u64>) -> Option<Matrix> {
+        let mut result = Matrix::identity(self.size);
+        let mut base = self.clone();
+
+        if let Some(m) = modulus {
+            result.cells.iter_mut().for_each(|cell| *cell %= m as u128);
+        }
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base, modulus)?;
+            }
+
+            exponent >>= 1;
+
+            if exponent > 0 {
+                base = base.mul(&base, modulus)?;
             }
         }
+
+        Some(result)
     }
+}
 
-    let individual_counts: HashMap<char, usize> = pairs
+fn element_counts(template: &[char], rules: &Rules, steps: u64, modulus: Option<u64>) -> Result<HashMap<char, u128>, Day14Error> {
+    if modulus == Some(0) {
+        return Err(Day14Error::ZeroModulus);
+    }
+
+    let elements: Vec<char> = template
         .iter()
-        .flat_map(|((a, _), count)| [(a, count)])
-        .interleave([(template.last().unwrap(), &1)].into_iter())
-        .fold(HashMap::new(), |mut acc, (c, count)| {
-            *acc.entry(*c).or_insert(0) += count;
-            acc
-        });
+        .chain(rules.iter().flat_map(|((a, b), c)| [a, b, c]))
+        .copied()
+        .unique()
+        .sorted()
+        .collect();
 
-    let (min, max) = individual_counts.values().minmax().into_option().unwrap();
+    let n = elements.len();
+    let index = |c: &char| elements.binary_search(c).unwrap();
+    let pair = |a: &char, b: &char| index(a) * n + index(b);
 
-    max - min
+    let mut transitions = Matrix { size: n * n, cells: vec![0; n * n * n * n] };
+
+    for (a, b) in elements.iter().cartesian_product(&elements) {
+        let from = pair(a, b) * n * n;
+
+        match rules.get(&(*a, *b)) {
+            Some(c) => {
+                transitions.cells[from + pair(a, c)] += 1;
+                transitions.cells[from + pair(c, b)] += 1;
+            },
+            None => transitions.cells[from + pair(a, b)] += 1,
+        }
+    }
+
+    let power = transitions.pow(steps, modulus).ok_or(Day14Error::Overflow(steps))?;
+    let reduce = |count: u128| modulus.map_or(count, |m| count % m as u128);
+
+    let mut counts: HashMap<char, u128> = HashMap::from([(*template.last().unwrap(), reduce(1))]);
+
+    for w in template.windows(2) {
+        let from = pair(&w[0], &w[1]) * n * n;
+
+        for (to, count) in power.cells[from..from + n * n].iter().enumerate() {
+            let total = counts.entry(elements[to / n]).or_insert(0);
+            *total = match modulus {
+                Some(m) => (*total + count) % m as u128,
+                None => total.checked_add(*count).ok_or(Day14Error::Overflow(steps))?,
+            };
+        }
+    }
+
+    Ok(counts)
+}
+
+#[aoc(day14, part1, missing)]
+fn part1_missing((template, rules): &(Vec<char>, Rules)) -> String {
+    match missing_rules(template, rules).as_slice() {
+        [] => "every pair that can occur has a rule".to_string(),
+        missing => format!("no rule for {}", missing.iter().map(|(a, b)| format!("{}{}", a, b)).join(", ")),
+    }
+}
+
+#[aoc(day14, part2)]
+fn part2((template, rules): &(Vec<char>, Rules)) -> Result<u128, Day14Error> {
+    let counts = element_counts(template, rules, 40, None)?;
+    let (min, max) = counts.values().minmax().into_option().unwrap();
+
+    Ok(max - min)
+}
+
+#[aoc(day14, part2, histogram)]
+fn part2_histogram((template, rules): &(Vec<char>, Rules)) -> String {
+    let lines = histograms(template, rules)
+        .take(41)
+        .enumerate()
+        .map(|(step, histogram)| {
+            let elements = histogram.elements.iter().sorted().map(|(element, count)| format!("{}={}", element, count)).join(" ");
+            format!("step {:>2}: {} ({} distinct pairs)", step, elements, histogram.pairs.len())
+        })
+        .join("\n");
+
+    format!("\n\n{}", lines)
+}
+
+#[aoc(day14, part2, trillion)]
+fn part2_trillion((template, rules): &(Vec<char>, Rules)) -> Result<String, Day14Error> {
+    let counts = element_counts(template, rules, 1_000_000_000_000, Some(1_000_000_007))?;
+
+    Ok(counts
+        .iter()
+        .sorted()
+        .map(|(element, count)| format!("{}: {}", element, count))
+        .join(", "))
 }
 
 #[cfg(test)]
@@ -83,11 +325,118 @@ mod tests {
 
     #[test]
     fn part1_example() {
-        assert_eq!(1588, part1(&parse(include_str!("../input/2021/day14.part1.test.1588.txt"))));
+        assert_eq!(1588, part1(&parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap()));
     }
 
     #[test]
     fn part2_example() {
-        assert_eq!(2188189693529, part2(&parse(include_str!("../input/2021/day14.part2.test.2188189693529.txt"))));
+        assert_eq!(2188189693529, part2(&parse(include_str!("../input/2021/day14.part2.test.2188189693529.txt")).unwrap()).unwrap());
+    }
+
+    fn pow_mod(mut base: u128, mut exponent: u64, m: u128) -> u128 {
+        let mut result = 1;
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base % m;
+            }
+
+            base = base * base % m;
+            exponent >>= 1;
+        }
+
+        result
+    }
+
+    #[test]
+    fn matrix_counts_match_polymer() {
+        let (template, rules) = parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap();
+        let counts = element_counts(&template, &rules, 10, None).unwrap();
+
+        assert_eq!(HashMap::from([('B', 1749), ('C', 298), ('H', 161), ('N', 865)]), counts);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn counts_modulo() {
+        let (template, rules) = parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap();
+        let m = 1_000_000_007;
+        let exact = element_counts(&template, &rules, 100, None).unwrap();
+        let reduced = element_counts(&template, &rules, 100, Some(m)).unwrap();
+
+        assert!(exact.iter().all(|(element, count)| reduced[element] == count % m as u128));
+        assert!(matches!(element_counts(&template, &rules, 200, None), Err(Day14Error::Overflow(200))));
+    }
+
+    #[test]
+    fn degenerate_moduli() {
+        let (template, rules) = parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap();
+
+        assert!(matches!(element_counts(&template, &rules, 10, Some(0)), Err(Day14Error::ZeroModulus)));
+        assert!(element_counts(&template, &rules, 0, Some(1)).unwrap().values().all(|count| *count == 0));
+        assert_eq!(HashMap::from([('B', 1), ('C', 1), ('H', 0), ('N', 0)]), element_counts(&template, &rules, 0, Some(2)).unwrap());
+        assert_eq!(Some(Matrix { size: 2, cells: vec![0; 4] }), Matrix::identity(2).pow(0, Some(1)));
+    }
+
+    #[test]
+    fn trillion_steps() {
+        let (template, rules) = parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap();
+        let (m, steps) = (998_244_353, 1_000_000_000_000);
+        let counts = element_counts(&template, &rules, steps, Some(m as u64)).unwrap();
+
+        // every pair has a rule, so the polymer's length doubles less one at each step
+        let length = (3 * pow_mod(2, steps, m) + 1) % m;
+
+        assert_eq!(4, counts.len());
+        assert_eq!(length, counts.values().sum::<u128>() % m);
+    }
+
+    #[test]
+    fn histograms_match_polymer() {
+        let (template, rules) = parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap();
+
+        for (steps, histogram) in histograms(&template, &rules).take(11).enumerate() {
+            let polymer = polymer(&template, &rules, steps);
+
+            assert_eq!(Histogram::new(&polymer), histogram);
+        }
+
+        assert_eq!("NBCCNBBBCBHCB", polymer(&template, &rules, 2).iter().collect::<String>());
+    }
+
+    #[test]
+    fn histograms_stop_before_overflow() {
+        let (template, rules) = parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap();
+        let last = histograms(&template, &rules).last().unwrap();
+        let exact = element_counts(&template, &rules, histograms(&template, &rules).count() as u64 - 1, None).unwrap();
+
+        assert_eq!(exact, last.elements);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(parse(""), Err(Day14Error::MissingTemplate)));
+        assert!(matches!(parse("NN\n\nNN -> C\nN -> C\n"), Err(Day14Error::InvalidRule(4, _))));
+        assert!(matches!(parse("NN\n\nNN -> N\nNN -> N\n"), Err(Day14Error::DuplicateRule(4, ('N', 'N')))));
+        assert!(matches!(parse("NN\n\nNN -> N\nNN -> C\n"), Err(Day14Error::ConflictingRule(4, ('N', 'N'), 'N', 'C'))));
+        assert!(parse("NN\n\nNN -> C\nNC -> N\n").is_ok());
+    }
+
+    #[test]
+    fn missing_rules_insert_nothing() {
+        let input = parse("NNCB\n\nNN -> C\nNC -> B\nCB -> H\nBC -> N\n").unwrap();
+        let (template, rules) = &input;
+
+        assert_eq!("no rule for BN, CH, CN, HB, NB", part1_missing(&input));
+        assert_eq!("every pair that can occur has a rule", part1_missing(&parse(include_str!("../input/2021/day14.part1.test.1588.txt")).unwrap()));
+
+        for (steps, histogram) in histograms(template, rules).take(8).enumerate() {
+            let polymer = polymer(template, rules, steps);
+            let counts = element_counts(template, rules, steps as u64, None).unwrap();
+
+            assert_eq!(Histogram::new(&polymer), histogram);
+            assert_eq!(histogram.elements, counts.into_iter().filter(|(_, count)| *count > 0).collect());
+        }
+
+        assert_eq!("NCNBCHB", polymer(template, rules, 1).iter().collect::<String>());
+    }
+}
@@ -1,63 +1,365 @@
 use std::cmp::Reverse;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::{BinaryHeap, HashSet};
+use std::ops::RangeInclusive;
 use aoc_runner_derive::{aoc, aoc_generator};
+use crate::grid::{Edges, Grid, GridError, Position};
+use crate::netpbm;
 
 #[aoc_generator(day15)]
-fn parse(input: &str) -> (HashMap<(i32, i32), i32>, i32) {
-    let size = input.lines().count();
+fn parse(input: &str) -> Result<Grid<u32>, GridError> {
+    Grid::parse_with(input, |c| c.to_digit(10).filter(|risk| *risk > 0))
+}
 
-    (
-        input
-            .lines()
-            .flat_map(|line| line.chars())
-            .map(|c| c.to_digit(10).unwrap() as i32)
-            .enumerate()
-            .map(|(i, v)| (((i / size) as i32, (i % size) as i32), v))
-            .collect(),
-        size as i32
-    )
+trait RiskMap {
+    fn dimensions(&self) -> (usize, usize);
+    fn risk(&self, position: Position) -> u32;
 }
 
-#[aoc(day15, part1)]
-fn part1((map, size): &(HashMap<(i32, i32), i32>, i32)) -> i32 {
-    let mut cumulative_risk: HashMap<(i32, i32), i32> = HashMap::new();
-    let mut queue: BinaryHeap<(Reverse<i32>, i32, i32)> = BinaryHeap::new();
+impl RiskMap for Grid<u32> {
+    fn dimensions(&self) -> (usize, usize) {
+        (self.height(), self.width())
+    }
+
+    fn risk(&self, position: Position) -> u32 {
+        self[position]
+    }
+}
+
+struct TiledMap<'a> {
+    tile: &'a Grid<u32>,
+    factor: usize,
+}
+
+impl RiskMap for TiledMap<'_> {
+    fn dimensions(&self) -> (usize, usize) {
+        (self.tile.height() * self.factor, self.tile.width() * self.factor)
+    }
+
+    fn risk(&self, (i, j): Position) -> u32 {
+        let (height, width) = (self.tile.height(), self.tile.width());
+        let risk = self.tile[(i % height, j % width)] + (i / height + j / width) as u32;
+
+        (risk + 8) % 9 + 1
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Strategy {
+    Dijkstra,
+    AStar,
+    Bidirectional,
+    BucketQueue,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct PathOptions {
+    strategy: Strategy,
+    diagonal: bool,
+    start: Position,
+    goal: Position,
+}
+
+impl PathOptions {
+    fn corners(map: &impl RiskMap) -> Self {
+        let (height, width) = map.dimensions();
+        PathOptions { strategy: Strategy::Dijkstra, diagonal: false, start: (0, 0), goal: (height - 1, width - 1) }
+    }
+}
 
-    cumulative_risk.insert((0, 0), 0);
-    queue.push((Reverse(0), 0, 0));
+/*
+ * Risks are in 1..=9, so a bucket queue only ever needs to look 9 steps ahead of the current distance,
+ * and A* can use the number of steps left to the goal as its heuristic. Moving diagonally, that is the
+ * Chebyshev rather than the Manhattan distance. Both strategies refuse maps with risks outside that range,
+ * and no strategy accepts a risk of 0, as a path could then wander between such cells at no cost.
+ */
+const MAX_RISK: u32 = 9;
+
+fn neighbors(labels: &Grid<u32>, position: Position, diagonal: bool) -> Vec<Position> {
+    match diagonal {
+        true => labels.neighbors8(position, Edges::Bounded).collect(),
+        false => labels.neighbors4(position, Edges::Bounded).collect(),
+    }
+}
+
+fn heuristic((i, j): Position, (goal_i, goal_j): Position, diagonal: bool) -> u32 {
+    let (di, dj) = (i.abs_diff(goal_i) as u32, j.abs_diff(goal_j) as u32);
+
+    match diagonal {
+        true => di.max(dj),
+        false => di + dj,
+    }
+}
+
+fn search_heap(map: &impl RiskMap, options: &PathOptions, use_heuristic: bool) -> Grid<u32> {
+    let (height, width) = map.dimensions();
+    let estimate = |position| match use_heuristic {
+        true => heuristic(position, options.goal, options.diagonal),
+        false => 0,
+    };
+    let mut cumulative_risk: Grid<u32> = Grid::from_fn(height, width, |_| u32::MAX);
+    let mut queue: BinaryHeap<(Reverse<u32>, Position)> = BinaryHeap::new();
+
+    cumulative_risk[options.start] = 0;
+    queue.push((Reverse(estimate(options.start)), options.start));
+
+    while let Some((Reverse(current_estimate), current)) = queue.pop() {
+        let current_cumulative_risk = cumulative_risk[current];
+
+        if current == options.goal {
+            break;
+        }
+
+        if current_estimate > current_cumulative_risk + estimate(current) {
+            continue;
+        }
+
+        for neighbor in neighbors(&cumulative_risk, current, options.diagonal) {
+            let neighbor_cumulative_risk = &mut cumulative_risk[neighbor];
+
+            if *neighbor_cumulative_risk > current_cumulative_risk + map.risk(neighbor) {
+                *neighbor_cumulative_risk = current_cumulative_risk + map.risk(neighbor);
+                queue.push((Reverse(*neighbor_cumulative_risk + estimate(neighbor)), neighbor));
+            }
+        }
+    }
+
+    cumulative_risk
+}
 
-    while let Some((Reverse(current_cumulative_risk), current_i, current_j)) = queue.pop() {
-        for (neighbor_i, neighbor_j) in [(current_i - 1, current_j), (current_i + 1, current_j), (current_i, current_j - 1), (current_i, current_j + 1)] {
-            if let Some(neighbor_risk) = map.get(&(neighbor_i, neighbor_j)) {
-                let neighbor_cumulative_risk = cumulative_risk.entry((neighbor_i, neighbor_j)).or_insert(i32::MAX);
+fn search_buckets(map: &impl RiskMap, options: &PathOptions) -> Grid<u32> {
+    let (height, width) = map.dimensions();
+    let num_buckets = MAX_RISK as usize + 1;
+    let mut cumulative_risk: Grid<u32> = Grid::from_fn(height, width, |_| u32::MAX);
+    let mut buckets: Vec<Vec<Position>> = vec![vec![]; num_buckets];
+    let mut remaining = 1;
 
-                if *neighbor_cumulative_risk > current_cumulative_risk + neighbor_risk {
-                    *neighbor_cumulative_risk = current_cumulative_risk + neighbor_risk;
-                    queue.push((Reverse(*neighbor_cumulative_risk), neighbor_i, neighbor_j));
+    cumulative_risk[options.start] = 0;
+    buckets[0].push(options.start);
+
+    for current_cumulative_risk in 0.. {
+        if remaining == 0 {
+            break;
+        }
+
+        while let Some(current) = buckets[current_cumulative_risk as usize % num_buckets].pop() {
+            remaining -= 1;
+
+            if cumulative_risk[current] != current_cumulative_risk {
+                continue;
+            }
+
+            if current == options.goal {
+                return cumulative_risk;
+            }
+
+            for neighbor in neighbors(&cumulative_risk, current, options.diagonal) {
+                let neighbor_cumulative_risk = current_cumulative_risk + map.risk(neighbor);
+
+                if cumulative_risk[neighbor] > neighbor_cumulative_risk {
+                    cumulative_risk[neighbor] = neighbor_cumulative_risk;
+                    buckets[neighbor_cumulative_risk as usize % num_buckets].push(neighbor);
+                    remaining += 1;
                 }
             }
         }
     }
 
-    cumulative_risk[&(size - 1, size - 1)]
+    cumulative_risk
 }
 
+/*
+ * The backward search labels every cell with the risk of getting from it to the goal, not counting the
+ * cell itself, so a cell labelled from both sides lies on a path of the sum of its labels. The searches
+ * can stop once the smallest labels left on both sides can't add up to anything better.
+ */
+fn search_bidirectional(map: &impl RiskMap, options: &PathOptions) -> Option<(u32, Vec<Position>)> {
+    let (height, width) = map.dimensions();
+    let mut labels = [Grid::from_fn(height, width, |_| u32::MAX), Grid::from_fn(height, width, |_| u32::MAX)];
+    let mut queues: [BinaryHeap<(Reverse<u32>, Position)>; 2] = [BinaryHeap::new(), BinaryHeap::new()];
+    let mut best = match options.start == options.goal {
+        true => Some((0, options.start)),
+        false => None,
+    };
 
-#[aoc(day15, part2)]
-fn part2((map, size): &(HashMap<(i32, i32), i32>, i32)) -> i32 {
-    let map: HashMap<(i32, i32), i32> = map.iter()
-        .flat_map(|((i, j), risk)| {
-            [
-                ((*i+size*0, *j+size*0), ((risk-1)%9)+1), ((*i+size*1, *j+size*0), ((risk+0)%9)+1), ((*i+size*2, *j+size*0), ((risk+1)%9)+1), ((*i+size*3, *j+size*0), ((risk+2)%9)+1), ((*i+size*4, *j+size*0), ((risk+3)%9)+1),
-                ((*i+size*0, *j+size*1), ((risk+0)%9)+1), ((*i+size*1, *j+size*1), ((risk+1)%9)+1), ((*i+size*2, *j+size*1), ((risk+2)%9)+1), ((*i+size*3, *j+size*1), ((risk+3)%9)+1), ((*i+size*4, *j+size*1), ((risk+4)%9)+1),
-                ((*i+size*0, *j+size*2), ((risk+1)%9)+1), ((*i+size*1, *j+size*2), ((risk+2)%9)+1), ((*i+size*2, *j+size*2), ((risk+3)%9)+1), ((*i+size*3, *j+size*2), ((risk+4)%9)+1), ((*i+size*4, *j+size*2), ((risk+5)%9)+1),
-                ((*i+size*0, *j+size*3), ((risk+2)%9)+1), ((*i+size*1, *j+size*3), ((risk+3)%9)+1), ((*i+size*2, *j+size*3), ((risk+4)%9)+1), ((*i+size*3, *j+size*3), ((risk+5)%9)+1), ((*i+size*4, *j+size*3), ((risk+6)%9)+1),
-                ((*i+size*0, *j+size*4), ((risk+3)%9)+1), ((*i+size*1, *j+size*4), ((risk+4)%9)+1), ((*i+size*2, *j+size*4), ((risk+5)%9)+1), ((*i+size*3, *j+size*4), ((risk+6)%9)+1), ((*i+size*4, *j+size*4), ((risk+7)%9)+1),
-            ]
+    labels[0][options.start] = 0;
+    labels[1][options.goal] = 0;
+    queues[0].push((Reverse(0), options.start));
+    queues[1].push((Reverse(0), options.goal));
+
+    while let (Some((Reverse(forward), _)), Some((Reverse(backward), _))) = (queues[0].peek(), queues[1].peek()) {
+        if best.is_some_and(|(risk, _)| forward + backward >= risk) {
+            break;
+        }
+
+        let side = match forward <= backward {
+            true => 0,
+            false => 1,
+        };
+        let (Reverse(current_label), current) = queues[side].pop().unwrap();
+
+        if current_label > labels[side][current] {
+            continue;
+        }
+
+        for neighbor in neighbors(&labels[side], current, options.diagonal) {
+            let neighbor_label = current_label + match side {
+                0 => map.risk(neighbor),
+                _ => map.risk(current),
+            };
+
+            if labels[side][neighbor] > neighbor_label {
+                labels[side][neighbor] = neighbor_label;
+                queues[side].push((Reverse(neighbor_label), neighbor));
+
+                let other_label = labels[1 - side][neighbor];
+
+                if other_label != u32::MAX && best.is_none_or(|(risk, _)| neighbor_label + other_label < risk) {
+                    best = Some((neighbor_label + other_label, neighbor));
+                }
+            }
+        }
+    }
+
+    let (risk, meeting) = best?;
+    let mut path = walk_labels(&labels[0], meeting, options.start, options.diagonal, |current, _| map.risk(current));
+    path.reverse();
+    path.extend(walk_labels(&labels[1], meeting, options.goal, options.diagonal, |_, next| map.risk(next)).into_iter().skip(1));
+
+    Some((risk, path))
+}
+
+/*
+ * Rather than keeping track of where every cell was reached from, the path is walked from a cell back to
+ * the origin of the search through any neighbor whose label accounts for the current one. This keeps the
+ * memory needed for large tilings down to a single label for every cell.
+ */
+fn walk_labels(labels: &Grid<u32>, from: Position, to: Position, diagonal: bool, risk: impl Fn(Position, Position) -> u32) -> Vec<Position> {
+    let mut path = vec![from];
+
+    while let Some(&current) = path.last().filter(|position| **position != to) {
+        let next = neighbors(labels, current, diagonal)
+            .into_iter()
+            .filter(|neighbor| !path.contains(neighbor))
+            .find(|neighbor| labels[*neighbor].checked_add(risk(current, *neighbor)) == Some(labels[current]))
+            .unwrap();
+
+        path.push(next);
+    }
+
+    path
+}
+
+fn has_risks_within(map: &impl RiskMap, bounds: RangeInclusive<u32>) -> bool {
+    let (height, width) = map.dimensions();
+
+    (0..height)
+        .flat_map(|i| (0..width).map(move |j| (i, j)))
+        .all(|position| bounds.contains(&map.risk(position)))
+}
+
+fn find_path(map: &impl RiskMap, options: &PathOptions) -> Option<(u32, Vec<Position>)> {
+    let (height, width) = map.dimensions();
+
+    if [options.start, options.goal].iter().any(|(i, j)| *i >= height || *j >= width) {
+        return None;
+    }
+
+    let max_risk = match options.strategy {
+        Strategy::AStar | Strategy::BucketQueue => MAX_RISK,
+        Strategy::Dijkstra | Strategy::Bidirectional => u32::MAX,
+    };
+
+    if !has_risks_within(map, 1..=max_risk) {
+        return None;
+    }
+
+    let cumulative_risk = match options.strategy {
+        Strategy::Dijkstra => search_heap(map, options, false),
+        Strategy::AStar => search_heap(map, options, true),
+        Strategy::BucketQueue => search_buckets(map, options),
+        Strategy::Bidirectional => return search_bidirectional(map, options),
+    };
+
+    let mut path = walk_labels(&cumulative_risk, options.goal, options.start, options.diagonal, |current, _| map.risk(current));
+    path.reverse();
+
+    Some((cumulative_risk[options.goal], path))
+}
+
+fn lowest_risk_path(map: &impl RiskMap) -> (u32, Vec<Position>) {
+    find_path(map, &PathOptions::corners(map)).unwrap()
+}
+
+fn render_path(map: &impl RiskMap, path: &[Position]) -> String {
+    let (height, width) = map.dimensions();
+    let on_path: HashSet<&Position> = path.iter().collect();
+
+    (0..height)
+        .map(|i| {
+            (0..width)
+                .map(|j| match on_path.contains(&(i, j)) {
+                    true => format!("\x1b[1m{}\x1b[0m", map.risk((i, j))),
+                    false => map.risk((i, j)).to_string(),
+                })
+                .collect::<String>() + "\n"
         })
-        .collect();
+        .collect()
+}
+
+fn to_pgm(map: &impl RiskMap) -> String {
+    let (height, width) = map.dimensions();
+    netpbm::pgm(height, width, MAX_RISK, |position| map.risk(position))
+}
+
+#[aoc(day15, part1)]
+fn part1(map: &Grid<u32>) -> u32 {
+    let (risk, _) = lowest_risk_path(map);
+    risk
+}
+
+#[aoc(day15, part1, path)]
+fn part1_path(map: &Grid<u32>) -> String {
+    let (risk, path) = lowest_risk_path(map);
+    format!("{}\n\n{}", risk, render_path(map, &path))
+}
+
+#[aoc(day15, part2)]
+fn part2(map: &Grid<u32>) -> u32 {
+    let (risk, _) = lowest_risk_path(&TiledMap { tile: map, factor: 5 });
+    risk
+}
+
+#[aoc(day15, part2, astar)]
+fn part2_astar(map: &Grid<u32>) -> u32 {
+    let map = TiledMap { tile: map, factor: 5 };
+    let (risk, _) = find_path(&map, &PathOptions { strategy: Strategy::AStar, ..PathOptions::corners(&map) }).unwrap();
+    risk
+}
 
-    part1(&(map, size * 5))
+#[aoc(day15, part2, bidirectional)]
+fn part2_bidirectional(map: &Grid<u32>) -> u32 {
+    let map = TiledMap { tile: map, factor: 5 };
+    let (risk, _) = find_path(&map, &PathOptions { strategy: Strategy::Bidirectional, ..PathOptions::corners(&map) }).unwrap();
+    risk
+}
+
+#[aoc(day15, part2, buckets)]
+fn part2_buckets(map: &Grid<u32>) -> u32 {
+    let map = TiledMap { tile: map, factor: 5 };
+    let (risk, _) = find_path(&map, &PathOptions { strategy: Strategy::BucketQueue, ..PathOptions::corners(&map) }).unwrap();
+    risk
+}
+
+#[aoc(day15, part2, path)]
+fn part2_path(map: &Grid<u32>) -> String {
+    let map = TiledMap { tile: map, factor: 5 };
+    let (risk, path) = lowest_risk_path(&map);
+    format!("{}\n\n{}", risk, render_path(&map, &path))
+}
+
+#[aoc(day15, part2, pgm)]
+fn part2_pgm(map: &Grid<u32>) -> Result<String, std::io::Error> {
+    netpbm::save("day15.pgm", &to_pgm(&TiledMap { tile: map, factor: 5 }))
 }
 
 #[cfg(test)]
@@ -66,16 +368,149 @@ mod tests {
 
     #[test]
     fn part1_example() {
-        assert_eq!(40, part1(&parse(include_str!("../input/2021/day15.part1.test.40.txt"))));
+        assert_eq!(40, part1(&parse(include_str!("../input/2021/day15.part1.test.40.txt")).unwrap()));
     }
 
     #[test]
     fn part2_example1() {
-        assert_eq!(315, part2(&parse(include_str!("../input/2021/day15.part2.test.315.txt"))));
+        assert_eq!(315, part2(&parse(include_str!("../input/2021/day15.part2.test.315.txt")).unwrap()));
     }
 
     #[test]
     fn part2_example2() {
-        assert_eq!(10, part1(&parse(include_str!("../input/2021/day15.part2.test.10.txt"))));
+        assert_eq!(10, part1(&parse(include_str!("../input/2021/day15.part2.test.10.txt")).unwrap()));
+    }
+
+    #[test]
+    fn part1_example_path() {
+        let map = parse(include_str!("../input/2021/day15.part1.test.40.txt")).unwrap();
+        let (risk, path) = lowest_risk_path(&map);
+
+        assert_eq!(40, risk);
+        assert_eq!(Some(&(0, 0)), path.first());
+        assert_eq!(Some(&(9, 9)), path.last());
+        assert_eq!(risk, path.iter().skip(1).map(|position| map[*position]).sum());
+        assert!(path.windows(2).all(|w| map.neighbors4(w[0], Edges::Bounded).any(|neighbor| neighbor == w[1])));
+    }
+
+    #[test]
+    fn part2_example_path() {
+        let tile = parse(include_str!("../input/2021/day15.part1.test.40.txt")).unwrap();
+        let map = TiledMap { tile: &tile, factor: 5 };
+        let (risk, path) = lowest_risk_path(&map);
+
+        assert_eq!(315, risk);
+        assert_eq!(Some(&(49, 49)), path.last());
+        assert_eq!(risk, path.iter().skip(1).map(|position| map.risk(*position)).sum());
+    }
+
+    #[test]
+    fn render_path_highlights_cells() {
+        let map = parse("19\n11").unwrap();
+        let (_, path) = lowest_risk_path(&map);
+
+        assert_eq!("\x1b[1m1\x1b[0m9\n\x1b[1m1\x1b[0m\x1b[1m1\x1b[0m\n", render_path(&map, &path));
+    }
+
+    #[test]
+    fn tiled_map_matches_materialized_map() {
+        let tile = parse("1163751742\n1381373672\n2136511328").unwrap();
+        let tiled = TiledMap { tile: &tile, factor: 7 };
+        let (height, width) = tiled.dimensions();
+        let materialized = Grid::from_fn(height, width, |position| tiled.risk(position));
+
+        assert_eq!((21, 70), (height, width));
+        assert_eq!(4, tiled.risk((0, 13)));
+        assert_eq!(5, tiled.risk((2, 69)));
+        assert_eq!(lowest_risk_path(&materialized), lowest_risk_path(&tiled));
+    }
+
+    #[test]
+    fn zero_risk_rejected() {
+        assert!(matches!(parse("19\n01"), Err(GridError::InvalidCell('0', (1, 0)))));
+        assert_eq!(9, TiledMap { tile: &Grid::from_fn(1, 1, |_| 0), factor: 1 }.risk((0, 0)));
+    }
+
+    fn assert_valid_path(map: &impl RiskMap, options: &PathOptions, risk: u32, path: &[Position]) {
+        let (height, width) = map.dimensions();
+        let grid = Grid::from_fn(height, width, |_| 0);
+
+        assert_eq!(Some(&options.start), path.first());
+        assert_eq!(Some(&options.goal), path.last());
+        assert_eq!(risk, path.iter().skip(1).map(|position| map.risk(*position)).sum());
+        assert!(path.windows(2).all(|w| neighbors(&grid, w[0], options.diagonal).contains(&w[1])));
+    }
+
+    #[test]
+    fn strategies_agree() {
+        let tile = parse(include_str!("../input/2021/day15.part1.test.40.txt")).unwrap();
+        let map = TiledMap { tile: &tile, factor: 3 };
+        let endpoints = [((0, 0), (29, 29)), ((29, 0), (0, 29)), ((12, 17), (3, 5)), ((7, 7), (7, 7)), ((0, 0), (0, 1))];
+
+        for (start, goal) in endpoints {
+            for diagonal in [false, true] {
+                let options = PathOptions { strategy: Strategy::Dijkstra, diagonal, start, goal };
+                let (expected, _) = find_path(&map, &options).unwrap();
+
+                for strategy in [Strategy::Dijkstra, Strategy::AStar, Strategy::Bidirectional, Strategy::BucketQueue] {
+                    let options = PathOptions { strategy, ..options };
+                    let (risk, path) = find_path(&map, &options).unwrap();
+
+                    assert_eq!(expected, risk, "{:?}", options);
+                    assert_valid_path(&map, &options, risk, &path);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn diagonal_movement_is_cheaper() {
+        let map = parse("19\n91").unwrap();
+
+        assert_eq!(Some(10), find_path(&map, &PathOptions::corners(&map)).map(|(risk, _)| risk));
+        assert_eq!(Some(1), find_path(&map, &PathOptions { diagonal: true, ..PathOptions::corners(&map) }).map(|(risk, _)| risk));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn endpoints_outside_map() {
+        let map = parse("19\n91").unwrap();
+        assert_eq!(None, find_path(&map, &PathOptions { goal: (2, 0), ..PathOptions::corners(&map) }));
+    }
+
+    #[test]
+    fn unbounded_risks() {
+        let map = Grid::from_fn(2, 2, |(i, j)| [[1, 12], [10, 1]][i][j]);
+        let options = PathOptions::corners(&map);
+
+        for strategy in [Strategy::Dijkstra, Strategy::Bidirectional] {
+            let options = PathOptions { strategy, ..options };
+            let (risk, path) = find_path(&map, &options).unwrap();
+
+            assert_eq!(11, risk);
+            assert_eq!(vec![(0, 0), (1, 0), (1, 1)], path);
+            assert_valid_path(&map, &options, risk, &path);
+        }
+
+        assert_eq!(None, find_path(&map, &PathOptions { strategy: Strategy::AStar, ..options }));
+        assert_eq!(None, find_path(&map, &PathOptions { strategy: Strategy::BucketQueue, ..options }));
+    }
+
+    #[test]
+    fn zero_risks() {
+        let map = Grid::from_fn(2, 2, |(i, j)| [[1, 12], [0, 1]][i][j]);
+        let row = Grid::from_fn(1, 4, |(_, j)| [0, 0, 5, 1][j]);
+
+        for strategy in [Strategy::Dijkstra, Strategy::AStar, Strategy::Bidirectional, Strategy::BucketQueue] {
+            assert_eq!(None, find_path(&map, &PathOptions { strategy, ..PathOptions::corners(&map) }));
+            assert_eq!(None, find_path(&row, &PathOptions { strategy, diagonal: false, start: (0, 3), goal: (0, 0) }));
+        }
+    }
+
+    #[test]
+    fn export_pgm() {
+        let map = Grid::parse_digits("19\n23\n").unwrap();
+
+        assert_eq!("P2\n2 2\n9\n1 9 2 3\n", to_pgm(&map));
+        assert_eq!("P2\n4 4\n9\n1 9 2 1 2 3 3 4 2 1 3 2 3 4 4 5\n", to_pgm(&TiledMap { tile: &map, factor: 2 }));
+    }
+}
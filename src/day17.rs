@@ -1,54 +1,268 @@
-use std::cmp::max;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
 use aoc_runner_derive::{aoc, aoc_generator};
-use std::num::ParseIntError;
 use itertools::Itertools;
 use regex::Regex;
 
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Target {
+    x: RangeInclusive<i32>,
+    y: RangeInclusive<i32>,
+}
+
+#[derive(Debug)]
+enum Day17Error {
+    InvalidTarget(String),
+    OutOfRange(String),
+    Unbounded(Target),
+    NoHits(Target),
+}
+
+impl Display for Day17Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Day17Error::InvalidTarget(input) => write!(f, "invalid target: {}", input),
+            Day17Error::OutOfRange(bound) => write!(f, "target bound {} is further than {} from the launcher", bound, MAX_DISTANCE),
+            Day17Error::Unbounded(target) => write!(f, "infinitely many velocities hit x={:?}, y={:?}", target.x, target.y),
+            Day17Error::NoHits(target) => write!(f, "no velocity hits x={:?}, y={:?}", target.x, target.y),
+        }
+    }
+}
+
+impl Error for Day17Error {}
+
+/*
+ * The solver below takes a number of steps proportional to the target's distance from the launcher, and
+ * squares it along the way, so targets are kept close enough for both to stay reasonable in an i32.
+ */
+const MAX_DISTANCE: i32 = 10_000;
+
 #[aoc_generator(day17)]
-fn parse(input: &str) -> Result<(i32, i32, i32, i32), ParseIntError> {
-    let re = Regex::new(r"target area: x=(\d+)..(\d+), y=(-?\d+)..(-?\d+)").unwrap();
-    let captures = re.captures(input).unwrap();
+fn parse(input: &str) -> Result<Target, Day17Error> {
+    let re = Regex::new(r"target area: x=(-?\d+)..(-?\d+), y=(-?\d+)..(-?\d+)").unwrap();
+    let captures = re.captures(input).ok_or_else(|| Day17Error::InvalidTarget(input.to_string()))?;
+    let bounds: Vec<i32> = (1..=4)
+        .map(|k| captures[k].parse().ok().filter(|bound: &i32| bound.abs() <= MAX_DISTANCE).ok_or_else(|| Day17Error::OutOfRange(captures[k].to_string())))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Target {
+        x: bounds[0].min(bounds[1])..=bounds[0].max(bounds[1]),
+        y: bounds[2].min(bounds[3])..=bounds[2].max(bounds[3]),
+    })
+}
+
+fn triangular(n: i32) -> i32 {
+    n * (n + 1) / 2
+}
+
+/*
+ * The smallest k >= 0 with triangular(k) >= a.
+ */
+fn triangular_root(a: i32) -> i32 {
+    if a <= 0 {
+        return 0;
+    }
+
+    let mut k = (((8 * a as i64 + 1).isqrt() - 1) / 2) as i32;
+
+    while triangular(k) < a {
+        k += 1;
+    }
+
+    k
+}
+
+fn div_floor(a: i32, n: i32) -> i32 {
+    a.div_euclid(n)
+}
+
+fn div_ceil(a: i32, n: i32) -> i32 {
+    -(-a).div_euclid(n)
+}
+
+/*
+ * After n steps a probe launched with vertical velocity v is at n*v - triangular(n - 1), so the velocities that
+ * are inside the target's rows at step n form a range. Horizontally the same holds while the probe is still
+ * moving, that is while v >= n; otherwise it has stalled at triangular(v).
+ */
+fn y_velocities(y: &RangeInclusive<i32>, n: i32) -> RangeInclusive<i32> {
+    div_ceil(y.start() + triangular(n - 1), n)..=div_floor(y.end() + triangular(n - 1), n)
+}
+
+/*
+ * Non-negative horizontal velocities at which the probe is within `lo..=hi` at step n.
+ */
+fn forward_x_velocities(lo: i32, hi: i32, n: i32) -> impl Iterator<Item = i32> {
+    let moving = div_ceil(lo + triangular(n - 1), n).max(n)..=div_floor(hi + triangular(n - 1), n);
+    let fastest_stalled = match hi >= 0 {
+        true => (triangular_root(hi + 1) - 1).min(n - 1),
+        false => -1,
+    };
+    let stalled = triangular_root(lo)..=fastest_stalled;
+
+    moving.chain(stalled)
+}
+
+/*
+ * Drag always acts towards x = 0, so leftward shots are mirror images of rightward ones.
+ */
+fn x_velocities(x: &RangeInclusive<i32>, n: i32) -> HashSet<i32> {
+    forward_x_velocities(*x.start(), *x.end(), n)
+        .chain(forward_x_velocities(-x.end(), -x.start(), n).filter(|v| *v > 0).map(|v| -v))
+        .collect()
+}
+
+/*
+ * Whether a probe can come to a stop horizontally inside the target.
+ */
+fn can_stall(x: &RangeInclusive<i32>) -> bool {
+    let forward = |lo: i32, hi: i32| hi >= 0 && triangular(triangular_root(lo)) <= hi;
+    forward(*x.start(), *x.end()) || forward(-x.end(), -x.start())
+}
+
+/*
+ * A probe thrown up faster than the target's furthest row from the launcher is already past it after the
+ * first step, and comes back down through the same heights until it's level with the launcher again, so
+ * vertical velocities and the steps until a hit are bounded by that distance. Only level with the launcher
+ * can it hit again, which happens for every upward velocity when the target spans the launcher's height and
+ * the probe can stall inside it. Otherwise a hit there needs the probe to still be moving horizontally, which
+ * bounds the steps by the target's furthest column.
+ */
+fn velocities(target: &Target) -> Result<HashSet<(i32, i32)>, Day17Error> {
+    if target.y.contains(&0) && can_stall(&target.x) {
+        return Err(Day17Error::Unbounded(target.clone()));
+    }
 
-    Ok((
-        captures.get(1).unwrap().as_str().parse()?,
-        captures.get(2).unwrap().as_str().parse()?,
-        captures.get(3).unwrap().as_str().parse()?,
-        captures.get(4).unwrap().as_str().parse()?,
-    ))
+    let y_bound = target.y.start().abs().max(target.y.end().abs());
+    let x_bound = target.x.start().abs().max(target.x.end().abs());
+    let mut velocities = HashSet::new();
+
+    for n in 1..=(2 * y_bound + 2).max(x_bound) {
+        let xs = x_velocities(&target.x, n);
+
+        for vy in y_velocities(&target.y, n) {
+            velocities.extend(xs.iter().map(|vx| (*vx, vy)));
+        }
+    }
+
+    Ok(velocities)
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Outcome {
+    Hit(usize),
+    Overshoot,
+    Undershoot,
+    TunnelThrough,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Outcome::Hit(step) => write!(f, "hit at step {}", step),
+            Outcome::Overshoot => write!(f, "overshoot"),
+            Outcome::Undershoot => write!(f, "undershoot"),
+            Outcome::TunnelThrough => write!(f, "tunnel through"),
+        }
+    }
 }
 
-fn is_valid(x_min: &i32, x_max: &i32, y_min: &i32, y_max: &i32, yv: &i32, xv: &i32) -> bool {
-    let (mut xv, mut yv) = (*xv, *yv);
+/*
+ * The probe is followed until it's in the target or can't reach it any more, either because it's falling below
+ * the target or because it's beside the target and not moving towards it. A probe within the target's columns
+ * that went from above the target to below it in a single step has dropped through it; one that never got
+ * above it fell short like any other.
+ */
+fn trace(target: &Target, (mut vx, mut vy): (i32, i32)) -> (Vec<(i32, i32)>, Outcome) {
     let (mut x, mut y) = (0, 0);
+    let mut positions = vec![];
+
+    loop {
+        let previous_y = y;
 
-    while x <= *x_max && y >= *y_min {
-        x += xv;
-        y += yv;
-        xv = max(0, xv - 1);
-        yv -= 1;
+        x += vx;
+        y += vy;
+        vx -= vx.signum();
+        vy -= 1;
+        positions.push((x, y));
 
-        if (x_min..=x_max).contains(&&x) && (y_min..=y_max).contains(&&y) {
-            return true;
+        if target.x.contains(&x) && target.y.contains(&y) {
+            let steps = positions.len();
+            return (positions, Outcome::Hit(steps));
+        }
+
+        let below = vy < 0 && y < *target.y.start();
+        let beside = (x > *target.x.end() && vx >= 0) || (x < *target.x.start() && vx <= 0);
+
+        if below || beside {
+            let beyond = match (*target.x.start() > 0, *target.x.end() < 0) {
+                (true, _) => x > *target.x.end(),
+                (_, true) => x < *target.x.start(),
+                _ => true,
+            };
+
+            let tunnelled = target.x.contains(&x) && previous_y > *target.y.end() && y < *target.y.start();
+            let outcome = match (tunnelled, beyond) {
+                (true, _) => Outcome::TunnelThrough,
+                (false, true) => Outcome::Overshoot,
+                (false, false) => Outcome::Undershoot,
+            };
+
+            return (positions, outcome);
         }
     }
+}
 
-    false
+/*
+ * Draws the shot the way the puzzle does, with the launcher at `S` and the target area as `T`.
+ */
+fn render(target: &Target, positions: &[(i32, i32)]) -> String {
+    let corners = [(0, 0), (*target.x.start(), *target.y.start()), (*target.x.end(), *target.y.end())];
+    let points = positions.iter().chain(&corners);
+    let (x_min, x_max) = points.clone().map(|(x, _)| *x).minmax().into_option().unwrap();
+    let (y_min, y_max) = points.map(|(_, y)| *y).minmax().into_option().unwrap();
+    let positions: HashSet<&(i32, i32)> = positions.iter().collect();
+
+    (y_min..=y_max)
+        .rev()
+        .map(|y| {
+            (x_min..=x_max)
+                .map(|x| match (x, y) {
+                    (0, 0) => 'S',
+                    _ if positions.contains(&(x, y)) => '#',
+                    _ if target.x.contains(&x) && target.y.contains(&y) => 'T',
+                    _ => '.',
+                })
+                .collect::<String>() + "\n"
+        })
+        .collect()
 }
 
 #[aoc(day17, part1)]
-fn part1((x_min, x_max, y_min, y_max): &(i32, i32, i32, i32)) -> i32 {
-    let (max_yv, _) = (0..=y_min.abs()).rev().cartesian_product(0..=*x_max)
-        .find(|(yv, xv)| is_valid(x_min, x_max, y_min, y_max, yv, xv))
-        .unwrap();
-
-    (max_yv*(max_yv+1))/2
+fn part1(target: &Target) -> Result<i32, Day17Error> {
+    velocities(target)?
+        .iter()
+        .map(|(_, vy)| triangular((*vy).max(0)))
+        .max()
+        .ok_or_else(|| Day17Error::NoHits(target.clone()))
 }
 
 #[aoc(day17, part2)]
-fn part2((x_min, x_max, y_min, y_max): &(i32, i32, i32, i32)) -> usize {
-    (-y_min.abs()..=y_min.abs()).rev().cartesian_product(0..=*x_max)
-        .filter(|(yv, xv)| is_valid(x_min, x_max, y_min, y_max, yv, xv))
-        .count()
+fn part2(target: &Target) -> Result<usize, Day17Error> {
+    Ok(velocities(target)?.len())
+}
+
+#[aoc(day17, part1, trace)]
+fn part1_trace(target: &Target) -> Result<String, Day17Error> {
+    let highest = velocities(target)?
+        .into_iter()
+        .max_by_key(|&(vx, vy)| (vy, -vx.abs()))
+        .ok_or_else(|| Day17Error::NoHits(target.clone()))?;
+    let (positions, outcome) = trace(target, highest);
+
+    Ok(format!("{:?}: {}\n\n{}", highest, outcome, render(target, &positions)))
 }
 
 #[cfg(test)]
@@ -57,11 +271,133 @@ mod tests {
 
     #[test]
     fn part1_example() {
-        assert_eq!(45, part1(&parse(include_str!("../input/2021/day17.part1.test.45.txt")).unwrap()));
+        assert_eq!(45, part1(&parse(include_str!("../input/2021/day17.part1.test.45.txt")).unwrap()).unwrap());
     }
 
     #[test]
     fn part2_example() {
-        assert_eq!(112, part2(&parse(include_str!("../input/2021/day17.part2.test.112.txt")).unwrap()));
+        assert_eq!(112, part2(&parse(include_str!("../input/2021/day17.part2.test.112.txt")).unwrap()).unwrap());
+    }
+
+    fn brute_force(target: &Target) -> HashSet<(i32, i32)> {
+        let bound = [target.x.start(), target.x.end(), target.y.start(), target.y.end()].iter().map(|v| v.abs()).max().unwrap();
+        let hits = |(mut vx, mut vy): (i32, i32)| {
+            let (mut x, mut y) = (0, 0);
+
+            (0..=2 * bound + 2).any(|_| {
+                x += vx;
+                y += vy;
+                vx -= vx.signum();
+                vy -= 1;
+
+                target.x.contains(&x) && target.y.contains(&y)
+            })
+        };
+
+        (-bound..=bound)
+            .flat_map(|vx| (-bound..=bound).map(move |vy| (vx, vy)))
+            .filter(|velocity| hits(*velocity))
+            .collect()
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let targets = [
+            "target area: x=20..30, y=-10..-5",
+            "target area: x=-30..-20, y=-10..-5",
+            "target area: x=20..30, y=5..10",
+            "target area: x=-30..-20, y=5..10",
+            "target area: x=-3..4, y=-6..-2",
+            "target area: x=11..14, y=-3..4",
+            "target area: x=-14..-11, y=0..0",
+        ];
+
+        for target in targets.iter().map(|input| parse(input).unwrap()) {
+            assert_eq!(brute_force(&target), velocities(&target).unwrap(), "{:?}", target);
+        }
+    }
+
+    #[test]
+    fn unbounded_targets() {
+        assert!(matches!(velocities(&parse("target area: x=10..14, y=-3..4").unwrap()), Err(Day17Error::Unbounded(_))));
+        assert!(matches!(velocities(&parse("target area: x=-12..-10, y=0..2").unwrap()), Err(Day17Error::Unbounded(_))));
+        assert!(matches!(velocities(&parse("target area: x=-2..2, y=-2..2").unwrap()), Err(Day17Error::Unbounded(_))));
+        assert!(velocities(&parse("target area: x=-2..2, y=-4..-2").unwrap()).is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn target_above_launcher() {
+        let target = parse("target area: x=20..30, y=5..10").unwrap();
+
+        let hits = velocities(&target).unwrap();
+
+        assert!(hits.contains(&(6, 10)));
+        assert!(!hits.contains(&(6, 11)));
+        assert_eq!(55, part1(&target).unwrap());
+    }
+
+    #[test]
+    fn targets_out_of_range() {
+        assert!(matches!(parse("target area: x=20..30, y=-10001..-5"), Err(Day17Error::OutOfRange(bound)) if bound == "-10001"));
+        assert!(matches!(parse("target area: x=20..99999999999, y=-10..-5"), Err(Day17Error::OutOfRange(_))));
+        assert!(matches!(parse("target area: x=20..30"), Err(Day17Error::InvalidTarget(_))));
+        assert_eq!(49995000, part1(&parse("target area: x=9000..10000, y=-10000..-9990").unwrap()).unwrap());
+    }
+
+    #[test]
+    fn trace_outcomes() {
+        let target = parse(include_str!("../input/2021/day17.part1.test.45.txt")).unwrap();
+
+        assert_eq!(Outcome::Hit(7), trace(&target, (7, 2)).1);
+        assert_eq!(Outcome::Hit(9), trace(&target, (6, 3)).1);
+        assert_eq!(Outcome::Hit(4), trace(&target, (9, 0)).1);
+        assert_eq!(Outcome::Overshoot, trace(&target, (17, -4)).1);
+        assert_eq!(Outcome::Undershoot, trace(&target, (1, 5)).1);
+        assert_eq!(Outcome::TunnelThrough, trace(&target, (6, 10)).1);
+        assert_eq!(Outcome::Undershoot, trace(&target, (-3, 0)).1);
+
+        let mirrored = parse("target area: x=-30..-20, y=-10..-5").unwrap();
+        assert_eq!(Outcome::Hit(7), trace(&mirrored, (-7, 2)).1);
+        assert_eq!(Outcome::Overshoot, trace(&mirrored, (-17, -4)).1);
+
+        let above = parse("target area: x=1..3, y=5..10").unwrap();
+        assert_eq!(Outcome::Undershoot, trace(&above, (1, 2)).1);
+        assert_eq!(Outcome::Hit(2), trace(&above, (1, 4)).1);
+        assert_eq!(Outcome::TunnelThrough, trace(&above, (1, 30)).1);
+        assert_eq!(Outcome::Overshoot, trace(&above, (5, 3)).1);
+    }
+
+    #[test]
+    fn trace_agrees_with_solver() {
+        let target = parse("target area: x=-3..4, y=-6..-2").unwrap();
+        let hits = velocities(&target).unwrap();
+
+        for velocity in (-8..=8).cartesian_product(-8..=8) {
+            assert_eq!(hits.contains(&velocity), matches!(trace(&target, velocity).1, Outcome::Hit(_)), "{:?}", velocity);
+        }
+    }
+
+    #[test]
+    fn render_like_puzzle() {
+        let target = parse(include_str!("../input/2021/day17.part1.test.45.txt")).unwrap();
+        let (positions, _) = trace(&target, (7, 2));
+        let expected = "\
+.............#....#............
+.......#..............#........
+...............................
+S........................#.....
+...............................
+...............................
+...........................#...
+...............................
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+....................TTTTTTTT#TT
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+";
+
+        assert_eq!(expected, render(&target, &positions));
+    }
+}
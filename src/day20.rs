@@ -1,14 +1,17 @@
-use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
+use crate::animation::{frames_to_string, Frame, Output};
+use crate::automaton::{evolve, run, Automaton, Boundary, Neighborhood, Stop};
+use crate::grid::{Grid, Position};
+use crate::netpbm;
 
 #[derive(Debug)]
 enum Day20Error {
     InvalidPixel(char),
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum Pixel {
     Dark,
     Light,
@@ -26,77 +29,189 @@ impl TryFrom<char> for Pixel {
     }
 }
 
-type Image = HashMap<(i32, i32), Pixel>;
+impl Display for Pixel {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Pixel::Dark => write!(f, "."),
+            Pixel::Light => write!(f, "#"),
+        }
+    }
+}
+
+/*
+ * The image is stored one bit per pixel, each row padded to whole words, together with the colour of the
+ * infinite background around it. Enhancing only ever affects the background-adjacent border, so every step
+ * grows the image by one pixel on each side and maps the background through the algorithm as a whole.
+ */
+#[derive(Clone, Eq, PartialEq)]
+struct Image {
+    height: usize,
+    width: usize,
+    words_per_row: usize,
+    bits: Vec<u64>,
+    background: Pixel,
+}
+
+impl Image {
+    fn new(height: usize, width: usize, background: Pixel) -> Self {
+        let words_per_row = width.div_ceil(64);
+
+        Image { height, width, words_per_row, bits: vec![0; height * words_per_row], background }
+    }
+
+    fn is_light(&self, i: isize, j: isize) -> bool {
+        match i >= 0 && j >= 0 && (i as usize) < self.height && (j as usize) < self.width {
+            true => {
+                let (i, j) = (i as usize, j as usize);
+                self.bits[i * self.words_per_row + j / 64] >> (j % 64) & 1 == 1
+            },
+            false => self.background == Pixel::Light,
+        }
+    }
+
+    fn set_light(&mut self, (i, j): (usize, usize)) {
+        self.bits[i * self.words_per_row + j / 64] |= 1 << (j % 64);
+    }
+
+    fn lit(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    /*
+     * Walking along a row, the 9-bit index of the next pixel is the previous index with its oldest column
+     * dropped and the newly uncovered column of three pixels shifted in, rather than nine fresh lookups.
+     */
+    fn enhance(&self, algorithm: &[Pixel]) -> Image {
+        let mut enhanced = Image::new(self.height + 2, self.width + 2, match self.background {
+            Pixel::Dark => algorithm[0],
+            Pixel::Light => algorithm[511],
+        });
+
+        let column = |i: isize, j: isize| {
+            (self.is_light(i - 1, j) as usize) << 6 | (self.is_light(i, j) as usize) << 3 | self.is_light(i + 1, j) as usize
+        };
+
+        for row in 0..enhanced.height {
+            let i = row as isize - 1;
+            let mut index = column(i, -2) << 1 | column(i, -1);
+
+            for col in 0..enhanced.width {
+                index = (index << 1) & 0b110_110_110 | column(i, col as isize);
+
+                if algorithm[index] == Pixel::Light {
+                    enhanced.set_light((row, col));
+                }
+            }
+        }
+
+        enhanced
+    }
+}
+
+impl From<&Grid<Pixel>> for Image {
+    fn from(grid: &Grid<Pixel>) -> Self {
+        let mut image = Image::new(grid.height(), grid.width(), Pixel::Dark);
+
+        for (position, pixel) in grid.iter() {
+            if *pixel == Pixel::Light {
+                image.set_light(position);
+            }
+        }
+
+        image
+    }
+}
+
+impl Display for Image {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for i in 0..self.height as isize {
+            for j in 0..self.width as isize {
+                write!(f, "{}", match self.is_light(i, j) {
+                    true => Pixel::Light,
+                    false => Pixel::Dark,
+                })?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Frame for Image {
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn glyph(&self, (i, j): Position) -> char {
+        match self.is_light(i as isize, j as isize) {
+            true => '#',
+            false => '.',
+        }
+    }
+
+    fn max_value(&self) -> u32 {
+        1
+    }
+
+    fn value(&self, (i, j): Position) -> u32 {
+        self.is_light(i as isize, j as isize) as u32
+    }
+}
 
 #[aoc_generator(day20)]
 fn parse(input: &str) -> (Vec<Pixel>, Image) {
     let (algorithm, image) = input.split_once("\n\n").unwrap();
     let algorithm = algorithm.chars().map(|c| c.try_into().unwrap()).collect::<Vec<_>>();
-    let image = image
-        .lines()
-        .enumerate()
-        .flat_map(|(i, line)| {
-            line.chars()
-                .enumerate()
-                .filter(|(_, c)| *c == '#')
-                .map(move |(j, c)| ((i as i32, j as i32, ), c.try_into().unwrap()))
-        })
-        .collect::<HashMap<_, _>>();
+    let image = Grid::parse_with(image, |c| c.try_into().ok()).unwrap();
 
-    (algorithm, image)
+    (algorithm, Image::from(&image))
 }
 
-fn image_bounds(image: &Image) -> (i32, i32, i32, i32) {
-    image
-        .iter()
-        .fold((0, 0, 0, 0), |(acc_min_i, acc_max_i, acc_min_j, acc_max_j), ((i, j), _)| (
-            min(acc_min_i, *i),
-            max(acc_max_i, *i),
-            min(acc_min_j, *j),
-            max(acc_max_j, *j),
-        ))
-}
-
-fn enhance(algorithm: &Vec<Pixel>, image: &Image, oob: Pixel) -> (Image, Pixel) {
-    let mut enhanced_image = HashMap::new();
-    let (min_i, max_i, min_j, max_j) = image_bounds(image);
-
-    for (i, j) in ((min_i-1)..=(max_i+1)).cartesian_product((min_j-1)..=(max_j+1)) {
-        let index_pixels: [(i32, i32); 9] = [
-            (i-1, j-1), (i-1, j), (i-1, j+1),
-            (i,   j-1), (i,   j), (i,   j+1),
-            (i+1, j-1), (i+1, j), (i+1, j+1),
-        ];
-
-        let index = index_pixels
+fn states<'a>(algorithm: &'a [Pixel], image: &Image) -> impl Iterator<Item = Image> + 'a {
+    evolve(image.clone(), |image| image.enhance(algorithm))
+}
+
+fn enhance_steps(algorithm: &[Pixel], image: &Image, steps: usize) -> Image {
+    states(algorithm, image).nth(steps).unwrap()
+}
+
+/*
+ * The same enhancement as a plain cellular automaton over an infinite grid, which the bit-packed image is a
+ * faster special case of.
+ */
+fn enhance_automaton(algorithm: &[Pixel], image: &Automaton<Pixel>) -> Automaton<Pixel> {
+    let window = Neighborhood::new((-1..=1).cartesian_product(-1..=1));
+
+    image.apply(&window, |_, pixels| {
+        let index = pixels
             .iter()
-            .map(|bit_index| match image.get(bit_index).unwrap_or(&oob) {
-                Pixel::Dark => 0,
-                Pixel::Light => 1,
-            })
-            .fold(0, |acc, bit| (acc << 1) + bit);
+            .fold(0, |acc, pixel| (acc << 1) | (*pixel == Pixel::Light) as usize);
 
-        enhanced_image.insert((i, j), algorithm[index]);
-    }
+        algorithm[index]
+    })
+}
 
-    let enhanced_oob: Pixel = match oob {
-        Pixel::Dark => algorithm[0],
-        Pixel::Light => algorithm[511],
-    };
+fn to_automaton(image: &Image) -> Automaton<Pixel> {
+    let grid = Grid::from_fn(image.height, image.width, |(i, j)| match image.is_light(i as isize, j as isize) {
+        true => Pixel::Light,
+        false => Pixel::Dark,
+    });
 
-    (enhanced_image, enhanced_oob)
+    Automaton::new(grid, Boundary::Infinite(image.background))
 }
 
-fn solve(algorithm: &Vec<Pixel>, image: &Image, steps: usize) -> usize {
-    let (image, _) = (0..steps).fold(
-        (image.clone(), Pixel::Dark),
-        |(image, obb), _step| enhance(algorithm, &image, obb)
-    );
+fn to_pbm(image: &Image) -> String {
+    netpbm::pbm(image.height, image.width, |(i, j)| image.is_light(i as isize, j as isize))
+}
 
-    image
-        .values()
-        .filter(|p| **p == Pixel::Light)
-        .count()
+fn solve(algorithm: &[Pixel], image: &Image, steps: usize) -> usize {
+    enhance_steps(algorithm, image, steps).lit()
 }
 
 #[aoc(day20, part1)]
@@ -109,6 +224,27 @@ fn part2((algorithm, image): &(Vec<Pixel>, Image)) -> usize {
     solve(algorithm, image, 50)
 }
 
+#[aoc(day20, part2, automaton)]
+fn part2_automaton((algorithm, image): &(Vec<Pixel>, Image)) -> usize {
+    let (_, image) = run(to_automaton(image), |image| enhance_automaton(algorithm, image), Stop::Steps(50));
+
+    image
+        .grid()
+        .iter()
+        .filter(|(_, p)| **p == Pixel::Light)
+        .count()
+}
+
+#[aoc(day20, part2, pbm)]
+fn part2_pbm((algorithm, image): &(Vec<Pixel>, Image)) -> Result<String, std::io::Error> {
+    netpbm::save("day20.pbm", &to_pbm(&enhance_steps(algorithm, image, 50)))
+}
+
+#[aoc(day20, part1, frames)]
+fn part1_frames((algorithm, image): &(Vec<Pixel>, Image)) -> String {
+    format!("\n\n{}", frames_to_string(states(algorithm, image), 0..=2, Output::Pgm))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -121,5 +257,44 @@ mod tests {
     #[test]
     fn part2_example() {
         assert_eq!(3351, part2(&parse(include_str!("../input/2021/day20.part2.test.3351.txt"))));
+        assert_eq!(3351, part2_automaton(&parse(include_str!("../input/2021/day20.part2.test.3351.txt"))));
+    }
+
+    #[test]
+    fn export_pbm() {
+        let (_, image) = parse(include_str!("../input/2021/day20.part1.test.35.txt"));
+
+        assert_eq!("P1\n5 5\n1001010000110010010000111\n", to_pbm(&image));
+    }
+
+    #[test]
+    fn matches_automaton_with_flashing_background() {
+        let algorithm: Vec<Pixel> = (0..512)
+            .map(|index: usize| match index {
+                0 => Pixel::Light,
+                511 => Pixel::Dark,
+                _ => if (index * 7919).is_multiple_of(3) { Pixel::Light } else { Pixel::Dark },
+            })
+            .collect();
+        let grid = Grid::from_fn(5, 70, |(i, j)| if (i * j + i).is_multiple_of(4) { Pixel::Light } else { Pixel::Dark });
+
+        let mut image = Image::from(&grid);
+        let mut reference = to_automaton(&image);
+
+        for _ in 0..4 {
+            image = image.enhance(&algorithm);
+            reference = enhance_automaton(&algorithm, &reference);
+
+            assert_eq!(reference, to_automaton(&image));
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn pgm_frames() {
+        let frames = part1_frames(&parse(include_str!("../input/2021/day20.part1.test.35.txt")));
+
+        assert_eq!(3, frames.matches("P2\n").count());
+        assert!(frames.trim_start().starts_with("P2\n5 5\n1\n1 0 0 1 0 1 0 0 0 0 1 1 0 0 1 0 0 1 0 0 0 0 1 1 1\nP2\n7 7\n1\n"));
+        assert!(frames.contains("P2\n9 9\n1\n"));
+    }
+}
@@ -1,75 +1,259 @@
-use std::cmp::{max, min};
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
 use aoc_runner_derive::{aoc, aoc_generator};
-use itertools::FoldWhile::{Continue, Done};
 use itertools::Itertools;
 
+#[derive(Debug)]
+enum Day21Error {
+    PlayerCountMismatch(usize, usize),
+}
+
+impl Display for Day21Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Day21Error::PlayerCountMismatch(expected, actual) => write!(f, "the rules are for {} players, but {} starting positions were given", expected, actual),
+        }
+    }
+}
+
+impl Error for Day21Error {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct GameRules {
+    board_size: u32,
+    target_score: u32,
+    die_faces: u32,
+    rolls_per_turn: u32,
+    players: usize,
+}
+
+impl GameRules {
+    fn deterministic() -> Self {
+        GameRules { board_size: 10, target_score: 1000, die_faces: 100, rolls_per_turn: 3, players: 2 }
+    }
+
+    fn dirac() -> Self {
+        GameRules { target_score: 21, die_faces: 3, ..GameRules::deterministic() }
+    }
+
+    fn check_players(&self, starting_positions: &[u32]) -> Result<(), Day21Error> {
+        match starting_positions.len() == self.players {
+            true => Ok(()),
+            false => Err(Day21Error::PlayerCountMismatch(self.players, starting_positions.len())),
+        }
+    }
+
+    fn advance(&self, position: u32, moves: u32) -> u32 {
+        ((position + moves - 1) % self.board_size) + 1
+    }
+
+    /*
+     * How many of the universes split off in one turn see each possible sum of the rolls.
+     */
+    fn roll_distribution(&self) -> Vec<(u32, usize)> {
+        let mut distribution: HashMap<u32, usize> = HashMap::from([(0, 1)]);
+
+        for _ in 0..self.rolls_per_turn {
+            let mut next: HashMap<u32, usize> = HashMap::new();
+
+            for ((sum, universes), face) in distribution.iter().cartesian_product(1..=self.die_faces) {
+                *next.entry(sum + face).or_default() += universes;
+            }
+
+            distribution = next;
+        }
+
+        distribution.into_iter().sorted().collect()
+    }
+}
+
 #[aoc_generator(day21)]
-fn parse(input: &str) -> (i32, i32) {
-    let nums: Vec<i32> = input
+fn parse(input: &str) -> Vec<u32> {
+    input
         .lines()
         .map(|line| line.split_once(": ").unwrap().1.parse().unwrap())
-        .collect();
-
-    (nums[0], nums[1])
+        .collect()
 }
 
-fn roll((position, score): &(i32, i32), step: i32) -> (i32, i32) {
-    let roll = step * 3;
-    let dice = (roll % 100) + 1 + ((roll + 1) % 100) + 1 + ((roll + 2) % 100) + 1;
-    let position = ((position + dice - 1) % 10) + 1;
-    let score = score + position;
+fn play_deterministic(rules: &GameRules, starting_positions: &[u32]) -> Result<u32, Day21Error> {
+    rules.check_players(starting_positions)?;
+
+    let mut players: Vec<(u32, u32)> = starting_positions.iter().map(|position| (*position, 0)).collect();
+    let mut die = (1..=rules.die_faces).cycle();
+    let mut rolls = 0;
+
+    for turn in 0.. {
+        let (position, score) = &mut players[turn % rules.players];
+        let moves: u32 = die.by_ref().take(rules.rolls_per_turn as usize).sum();
+
+        *position = rules.advance(*position, moves);
+        *score += *position;
+        rolls += rules.rolls_per_turn;
+
+        if *score >= rules.target_score {
+            break;
+        }
+    }
 
-    (position, score)
+    Ok(players.iter().map(|(_, score)| *score).min().unwrap() * rolls)
 }
 
 #[aoc(day21, part1)]
-fn part1((position_p1, position_p2): &(i32, i32)) -> i32 {
-    let (p1, p2, rolls) = (0..)
-        .fold_while(((*position_p1, 0), (*position_p2, 0), 0), |(p1, p2, _), step| {
-            let (p1, p2) = match step % 2 {
-                0 => (roll(&p1, step), p2),
-                1 => (p1, roll(&p2, step)),
-                _ => unreachable!()
-            };
-
-            if p1.1 >= 1000 || p2.1 >= 1000 {
-                return Done((p1, p2, (step + 1) * 3));
-            }
+fn part1(starting_positions: &[u32]) -> Result<u32, Day21Error> {
+    play_deterministic(&GameRules::deterministic(), starting_positions)
+}
+
+type Cache = HashMap<Vec<(u32, u32)>, Vec<usize>>;
+
+/*
+ * Players are given as (position, score) with the player whose turn it is first, and the result counts the
+ * universes each of them wins in that same order. After moving, the player goes to the back of the queue.
+ */
+fn simulate(rules: &GameRules, distribution: &[(u32, usize)], cache: &mut Cache, players: &[(u32, u32)]) -> Vec<usize> {
+    if let Some(cached_result) = cache.get(players) {
+        return cached_result.clone();
+    }
+
+    let mut wins = vec![0; players.len()];
+    let (position, score) = players[0];
+
+    for &(moves, universes) in distribution {
+        let position = rules.advance(position, moves);
+        let score = score + position;
+
+        if score >= rules.target_score {
+            wins[0] += universes;
+            continue;
+        }
+
+        let next_players: Vec<(u32, u32)> = players[1..].iter().copied().chain([(position, score)]).collect();
+        let next_wins = simulate(rules, distribution, cache, &next_players);
+
+        for (k, w) in next_wins.iter().enumerate() {
+            wins[(k + 1) % players.len()] += universes * w;
+        }
+    }
+
+    cache.insert(players.to_vec(), wins.clone());
+
+    wins
+}
+
+fn count_wins(rules: &GameRules, starting_positions: &[u32]) -> Result<Vec<usize>, Day21Error> {
+    rules.check_players(starting_positions)?;
+
+    let players: Vec<(u32, u32)> = starting_positions.iter().map(|position| (*position, 0)).collect();
+    Ok(simulate(rules, &rules.roll_distribution(), &mut HashMap::new(), &players))
+}
 
-            Continue((p1, p2, (step + 1) * 3))
-        }).into_inner();
+#[aoc(day21, part2)]
+fn part2(starting_positions: &[u32]) -> Result<usize, Day21Error> {
+    Ok(count_wins(&GameRules::dirac(), starting_positions)?.into_iter().max().unwrap())
+}
 
-    min(p1.1, p2.1) * rolls
+/*
+ * Counting universes treats a game won on the third turn the same as one won on the tenth, even though the
+ * latter stands for far less of the total probability. Here the games are played forward one turn at a time
+ * instead, carrying the probability of every reachable state until all of them have been won.
+ */
+#[derive(Debug, Clone, PartialEq)]
+struct Analysis {
+    winning_turn: Vec<f64>,
+    win_probability: Vec<f64>,
+    expected_turns: f64,
 }
 
-type Cache = HashMap<((i32, i32), (i32, i32)), (usize, usize)>;
+fn analyze(rules: &GameRules, starting_positions: &[u32]) -> Result<Analysis, Day21Error> {
+    rules.check_players(starting_positions)?;
+
+    let distribution = rules.roll_distribution();
+    let total_universes: usize = distribution.iter().map(|(_, universes)| universes).sum();
+
+    let mut states: HashMap<Vec<(u32, u32)>, f64> = HashMap::from([(starting_positions.iter().map(|position| (*position, 0)).collect(), 1.0)]);
+    let mut winning_turn = vec![];
+    let mut win_probability = vec![0.0; rules.players];
+
+    for player in (0..rules.players).cycle() {
+        if states.is_empty() {
+            break;
+        }
 
-fn simulate(cache: &mut Cache, (position, score): (i32, i32), other_player: (i32, i32)) -> (usize, usize) {
-    if other_player.1 >= 21 {
-        return (0, 1);
+        let mut won = 0.0;
+        let mut next_states: HashMap<Vec<(u32, u32)>, f64> = HashMap::new();
+
+        for (players, probability) in states {
+            let (position, score) = players[player];
+
+            for &(moves, universes) in &distribution {
+                let probability = probability * universes as f64 / total_universes as f64;
+                let position = rules.advance(position, moves);
+
+                if score + position >= rules.target_score {
+                    won += probability;
+                } else {
+                    let mut players = players.clone();
+                    players[player] = (position, score + position);
+                    *next_states.entry(players).or_default() += probability;
+                }
+            }
+        }
+
+        winning_turn.push(won);
+        win_probability[player] += won;
+        states = next_states;
     }
 
-    if let Some(&cached_result) = cache.get(&((position, score), other_player)) {
-        return cached_result;
+    let expected_turns = winning_turn.iter().enumerate().map(|(turn, p)| (turn + 1) as f64 * p).sum();
+
+    Ok(Analysis { winning_turn, win_probability, expected_turns })
+}
+
+impl Display for Analysis {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for (player, p) in self.win_probability.iter().enumerate() {
+            writeln!(f, "player {} wins with probability {:.6}", player + 1, p)?;
+        }
+
+        writeln!(f, "expected number of turns: {:.3}", self.expected_turns)?;
+
+        for (turn, p) in self.winning_turn.iter().enumerate().filter(|(_, p)| **p > 0.0) {
+            writeln!(f, "won on turn {:>2}: {:.6}", turn + 1, p)?;
+        }
+
+        Ok(())
     }
+}
 
-    let result = [ (3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1) ].iter()
-        .fold((0, 0), |(current_player_wins, other_player_wins), &(outcome, multiplier)| {
-            let position = ((position + outcome - 1) % 10) + 1;
-            let wins = simulate(cache, other_player, (position, score + position));
-            (current_player_wins + multiplier * wins.1, other_player_wins + multiplier * wins.0)
-        });
-    cache.insert(((position, score), other_player), result);
+/*
+ * Probability of the first player winning, for every pair of starting positions.
+ */
+fn win_probabilities(rules: &GameRules) -> Result<Vec<Vec<f64>>, Day21Error> {
+    (1..=rules.board_size)
+        .map(|first| {
+            (1..=rules.board_size)
+                .map(|second| Ok(analyze(rules, &[first, second])?.win_probability[0]))
+                .collect()
+        })
+        .collect()
+}
 
-    result
+#[aoc(day21, part2, analysis)]
+fn part2_analysis(starting_positions: &[u32]) -> Result<String, Day21Error> {
+    Ok(format!("\n\n{}", analyze(&GameRules::dirac(), starting_positions)?))
 }
 
-#[aoc(day21, part2)]
-fn part2((position_p1, position_p2): &(i32, i32)) -> usize {
-    let mut cache: Cache = HashMap::new();
-    let (wins_p1, wins_p2) = simulate(&mut cache, (*position_p1, 0), (*position_p2, 0));
-    max(wins_p1, wins_p2)
+#[aoc(day21, part2, table)]
+fn part2_table(_: &[u32]) -> Result<String, Day21Error> {
+    let probabilities = win_probabilities(&GameRules::dirac())?;
+    let header = (1..=probabilities.len()).map(|second| format!("{:>6}", second)).join("");
+    let rows = probabilities
+        .iter()
+        .enumerate()
+        .map(|(first, row)| format!("{:>4}  {}", first + 1, row.iter().map(|p| format!("{:>6.3}", p)).join("")))
+        .join("\n");
+
+    Ok(format!("\n\n   1\\2{}\n{}\n", header, rows))
 }
 
 #[cfg(test)]
@@ -78,11 +262,72 @@ mod tests {
 
     #[test]
     fn part1_example() {
-        assert_eq!(739785, part1(&parse(include_str!("../input/2021/day21.part1.test.739785.txt"))));
+        assert_eq!(739785, part1(&parse(include_str!("../input/2021/day21.part1.test.739785.txt"))).unwrap());
     }
 
     #[test]
     fn part2_example() {
-        assert_eq!(444356092776315, part2(&parse(include_str!("../input/2021/day21.part2.test.444356092776315.txt"))));
+        assert_eq!(444356092776315, part2(&parse(include_str!("../input/2021/day21.part2.test.444356092776315.txt"))).unwrap());
+    }
+
+    #[test]
+    fn dirac_roll_distribution() {
+        assert_eq!(vec![(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)], GameRules::dirac().roll_distribution());
+        assert_eq!(vec![(1, 1), (2, 1), (3, 1), (4, 1)], GameRules { die_faces: 4, rolls_per_turn: 1, ..GameRules::dirac() }.roll_distribution());
+    }
+
+    #[test]
+    fn other_rules() {
+        let instant = GameRules { target_score: 1, ..GameRules::dirac() };
+        assert_eq!(vec![27, 0], count_wins(&instant, &[4, 8]).unwrap());
+
+        let three_players = GameRules { target_score: 4, die_faces: 1, rolls_per_turn: 1, players: 3, ..GameRules::dirac() };
+        assert_eq!(vec![1, 0, 0], count_wins(&three_players, &[1, 1, 1]).unwrap());
+        assert_eq!(vec![0, 0, 1], count_wins(&three_players, &[1, 1, 3]).unwrap());
+
+        let short_board = GameRules { board_size: 4, target_score: 6, die_faces: 2, rolls_per_turn: 1, players: 2 };
+        assert_eq!(20, play_deterministic(&short_board, &[1, 2]).unwrap());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn player_count_mismatch() {
+        assert!(matches!(play_deterministic(&GameRules::deterministic(), &[4, 8, 1]), Err(Day21Error::PlayerCountMismatch(2, 3))));
+        assert!(matches!(count_wins(&GameRules::dirac(), &[4]), Err(Day21Error::PlayerCountMismatch(2, 1))));
+        assert!(matches!(analyze(&GameRules::dirac(), &[]), Err(Day21Error::PlayerCountMismatch(2, 0))));
+    }
+
+    #[test]
+    fn analysis_of_example() {
+        let analysis = analyze(&GameRules::dirac(), &parse(include_str!("../input/2021/day21.part2.test.444356092776315.txt"))).unwrap();
+
+        assert!((analysis.win_probability.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((analysis.winning_turn.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert!((analysis.winning_turn.iter().step_by(2).sum::<f64>() - analysis.win_probability[0]).abs() < 1e-9);
+        assert!(analysis.expected_turns > 6.0 && analysis.expected_turns < analysis.winning_turn.len() as f64);
+        assert!(analysis.winning_turn[..4].iter().all(|p| *p == 0.0));
+        assert!(analysis.winning_turn[4] > 0.0);
+    }
+
+    #[test]
+    fn analysis_of_single_outcome() {
+        let rules = GameRules { target_score: 4, die_faces: 1, rolls_per_turn: 1, players: 3, ..GameRules::dirac() };
+        let analysis = analyze(&rules, &[1, 1, 3]).unwrap();
+
+        assert_eq!(vec![0.0, 0.0, 1.0], analysis.win_probability);
+        assert_eq!(vec![0.0, 0.0, 1.0], analysis.winning_turn);
+        assert_eq!(3.0, analysis.expected_turns);
+    }
+
+    #[test]
+    fn win_probability_table() {
+        let rules = GameRules { board_size: 4, target_score: 8, ..GameRules::dirac() };
+        let probabilities = win_probabilities(&rules).unwrap();
+
+        assert_eq!((4, 4), (probabilities.len(), probabilities[0].len()));
+        assert!(probabilities.iter().flatten().all(|p| (0.0..=1.0).contains(p)));
+        assert!((analyze(&rules, &[2, 3]).unwrap().win_probability[0] - probabilities[1][2]).abs() < 1e-9);
+
+        let wins = count_wins(&rules, &[2, 3]).unwrap();
+        assert!((wins[0] as f64 / (wins[0] + wins[1]) as f64 - probabilities[1][2]).abs() > 1e-3);
+    }
+}
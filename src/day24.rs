@@ -1,7 +1,252 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+use std::str::FromStr;
 use aoc_runner_derive::{aoc, aoc_generator};
 
+#[derive(Debug)]
+enum Day24Error {
+    InvalidInstruction(String),
+    InvalidRegister(String),
+    MissingInput,
+    DivisionByZero,
+    InvalidModulo(i64, i64),
+    Overflow(i64, Operator, i64),
+    InputLengthMismatch(usize, usize),
+    NoValidInput,
+}
+
+impl Display for Day24Error {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Day24Error::InvalidInstruction(instruction) => write!(f, "invalid instruction: {}", instruction),
+            Day24Error::InvalidRegister(register) => write!(f, "invalid register: {}", register),
+            Day24Error::MissingInput => write!(f, "program read past the end of the input"),
+            Day24Error::DivisionByZero => write!(f, "division by zero"),
+            Day24Error::InvalidModulo(a, b) => write!(f, "invalid modulo: {} % {}", a, b),
+            Day24Error::Overflow(a, operator, b) => write!(f, "overflow: {} {} {}", a, operator, b),
+            Day24Error::InputLengthMismatch(expected, actual) => write!(f, "expected {} inputs, program reads {}", expected, actual),
+            Day24Error::NoValidInput => write!(f, "no input is accepted by the program"),
+        }
+    }
+}
+
+impl Error for Day24Error {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+enum Register {
+    W,
+    X,
+    Y,
+    Z,
+}
+
+impl FromStr for Register {
+    type Err = Day24Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "w" => Ok(Register::W),
+            "x" => Ok(Register::X),
+            "y" => Ok(Register::Y),
+            "z" => Ok(Register::Z),
+            _ => Err(Day24Error::InvalidRegister(s.to_string())),
+        }
+    }
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Register::W => write!(f, "w"),
+            Register::X => write!(f, "x"),
+            Register::Y => write!(f, "y"),
+            Register::Z => write!(f, "z"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Operand {
+    Register(Register),
+    Literal(i64),
+}
+
+impl FromStr for Operand {
+    type Err = Day24Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse::<i64>() {
+            Ok(literal) => Ok(Operand::Literal(literal)),
+            Err(_) => Ok(Operand::Register(s.parse()?)),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Operand::Register(register) => write!(f, "{}", register),
+            Operand::Literal(literal) => write!(f, "{}", literal),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Operator {
+    Add,
+    Mul,
+    Div,
+    Mod,
+    Eql,
+}
+
+impl Operator {
+    fn apply(&self, a: i64, b: i64) -> Result<i64, Day24Error> {
+        match self {
+            Operator::Add => a.checked_add(b).ok_or(Day24Error::Overflow(a, *self, b)),
+            Operator::Mul => a.checked_mul(b).ok_or(Day24Error::Overflow(a, *self, b)),
+            Operator::Div if b == 0 => Err(Day24Error::DivisionByZero),
+            Operator::Div => a.checked_div(b).ok_or(Day24Error::Overflow(a, *self, b)),
+            Operator::Mod if a < 0 || b <= 0 => Err(Day24Error::InvalidModulo(a, b)),
+            Operator::Mod => Ok(a % b),
+            Operator::Eql => Ok((a == b) as i64),
+        }
+    }
+
+    fn range(&self, (a_min, a_max): (i64, i64), (b_min, b_max): (i64, i64)) -> (i64, i64) {
+        let corners = |f: fn(i64, i64) -> i64| {
+            let values = [f(a_min, b_min), f(a_min, b_max), f(a_max, b_min), f(a_max, b_max)];
+            (*values.iter().min().unwrap(), *values.iter().max().unwrap())
+        };
+
+        match self {
+            Operator::Add => (a_min.saturating_add(b_min), a_max.saturating_add(b_max)),
+            Operator::Mul => corners(i64::saturating_mul),
+            Operator::Div if b_min > 0 || b_max < 0 => corners(i64::saturating_div),
+            Operator::Div => {
+                let bound = a_min.saturating_abs().max(a_max.saturating_abs());
+                (-bound, bound)
+            },
+            Operator::Mod => (0, a_max.max(0).min(b_max.saturating_sub(1))),
+            Operator::Eql => (0, 1),
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Mul => write!(f, "*"),
+            Operator::Div => write!(f, "/"),
+            Operator::Mod => write!(f, "%"),
+            Operator::Eql => write!(f, "=="),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Instruction {
+    Inp(Register),
+    Add(Register, Operand),
+    Mul(Register, Operand),
+    Div(Register, Operand),
+    Mod(Register, Operand),
+    Eql(Register, Operand),
+}
+
+impl FromStr for Instruction {
+    type Err = Day24Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+
+        match parts.as_slice() {
+            ["inp", a] => Ok(Instruction::Inp(a.parse()?)),
+            ["add", a, b] => Ok(Instruction::Add(a.parse()?, b.parse()?)),
+            ["mul", a, b] => Ok(Instruction::Mul(a.parse()?, b.parse()?)),
+            ["div", a, b] => Ok(Instruction::Div(a.parse()?, b.parse()?)),
+            ["mod", a, b] => Ok(Instruction::Mod(a.parse()?, b.parse()?)),
+            ["eql", a, b] => Ok(Instruction::Eql(a.parse()?, b.parse()?)),
+            _ => Err(Day24Error::InvalidInstruction(s.to_string())),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Instruction::Inp(a) => write!(f, "inp {}", a),
+            Instruction::Add(a, b) => write!(f, "add {} {}", a, b),
+            Instruction::Mul(a, b) => write!(f, "mul {} {}", a, b),
+            Instruction::Div(a, b) => write!(f, "div {} {}", a, b),
+            Instruction::Mod(a, b) => write!(f, "mod {} {}", a, b),
+            Instruction::Eql(a, b) => write!(f, "eql {} {}", a, b),
+        }
+    }
+}
+
+impl Instruction {
+    fn operation(&self) -> Option<(Operator, Register, Operand)> {
+        match *self {
+            Instruction::Inp(_) => None,
+            Instruction::Add(a, b) => Some((Operator::Add, a, b)),
+            Instruction::Mul(a, b) => Some((Operator::Mul, a, b)),
+            Instruction::Div(a, b) => Some((Operator::Div, a, b)),
+            Instruction::Mod(a, b) => Some((Operator::Mod, a, b)),
+            Instruction::Eql(a, b) => Some((Operator::Eql, a, b)),
+        }
+    }
+
+    fn target(&self) -> Register {
+        match *self {
+            Instruction::Inp(a) | Instruction::Add(a, _) | Instruction::Mul(a, _) |
+            Instruction::Div(a, _) | Instruction::Mod(a, _) | Instruction::Eql(a, _) => a,
+        }
+    }
+
+    fn reads(&self) -> Vec<Register> {
+        match *self {
+            Instruction::Inp(_) => vec![],
+            Instruction::Mul(_, Operand::Literal(0)) => vec![],
+            Instruction::Add(a, b) | Instruction::Mul(a, b) | Instruction::Div(a, b) |
+            Instruction::Mod(a, b) | Instruction::Eql(a, b) => match b {
+                Operand::Register(b) => vec![a, b],
+                Operand::Literal(_) => vec![a],
+            },
+        }
+    }
+}
+
+type Registers = [i64; 4];
+
+fn value(registers: &Registers, operand: Operand) -> i64 {
+    match operand {
+        Operand::Register(register) => registers[register as usize],
+        Operand::Literal(literal) => literal,
+    }
+}
+
+fn execute(instruction: &Instruction, registers: &mut Registers, input: &mut impl Iterator<Item = i64>) -> Result<(), Day24Error> {
+    let result = match instruction.operation() {
+        None => input.next().ok_or(Day24Error::MissingInput)?,
+        Some((operator, a, b)) => operator.apply(registers[a as usize], value(registers, b))?,
+    };
+
+    registers[instruction.target() as usize] = result;
+
+    Ok(())
+}
+
 #[aoc_generator(day24)]
-fn parse(_input: &str) -> () {}
+fn parse(input: &str) -> Result<Vec<Instruction>, Day24Error> {
+    input
+        .lines()
+        .map(|line| line.parse())
+        .collect()
+}
 
 /*
  * The input can be split up in 14 nearly identical sections, only differing on the literal value for
@@ -109,46 +354,542 @@ fn parse(_input: &str) -> () {}
  *     input[ 3] = input[2] + 13 - 6
  */
 
+
+fn monad_constants(program: &[Instruction]) -> Option<Vec<(i64, i64, i64)>> {
+    let template: Vec<Instruction> = parse("inp w\nmul x 0\nadd x z\nmod x 26\ndiv z 1\nadd x 0\neql x w\neql x 0\nmul y 0\nadd y 25\nmul y x\nadd y 1\nmul z y\nmul y 0\nadd y w\nadd y 0\nmul y x\nadd z y").unwrap();
+
+    if !program.len().is_multiple_of(template.len()) {
+        return None;
+    }
+
+    program
+        .chunks(template.len())
+        .map(|block| {
+            let matches_template = block
+                .iter()
+                .zip(&template)
+                .enumerate()
+                .all(|(k, (instruction, expected))| [4, 5, 15].contains(&k) || instruction == expected);
+
+            match (matches_template, block[4], block[5], block[15]) {
+                (true, Instruction::Div(Register::Z, Operand::Literal(a)), Instruction::Add(Register::X, Operand::Literal(b)), Instruction::Add(Register::Y, Operand::Literal(c)))
+                    if (a == 1 && b > 9) || a == 26 => Some((a, b, c)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn solve_monad(constants: &[(i64, i64, i64)], largest: bool) -> Option<Vec<i64>> {
+    let mut input = vec![0; constants.len()];
+    let mut stack = vec![];
+
+    for (i, &(a, b, c)) in constants.iter().enumerate() {
+        if a == 1 {
+            stack.push((i, c));
+            continue;
+        }
+
+        let (j, c) = stack.pop()?;
+        let difference = c + b;
+
+        input[j] = match largest {
+            true => 9 - difference.max(0),
+            false => 1 - difference.min(0),
+        };
+        input[i] = input[j] + difference;
+
+        if !(1..=9).contains(&input[j]) || !(1..=9).contains(&input[i]) {
+            return None;
+        }
+    }
+
+    match stack.is_empty() {
+        true => Some(input),
+        false => None,
+    }
+}
+
+/*
+ * Programs that don't follow the pattern above are instead searched digit by digit. The program is
+ * split into blocks starting at each `inp` instruction, and since every block is deterministic given
+ * the register state it starts from and the digit it reads, a state that has failed once will always
+ * fail. Failed states are remembered per block, keyed only on the registers that are read before they
+ * are written in the rest of the program (only `z` for MONAD), which is what keeps the search space
+ * small. Trying the alphabet in descending or ascending order makes the first accepted input the
+ * largest or the smallest one.
+ */
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct SearchOptions {
+    input_length: usize,
+    alphabet: Vec<i64>,
+}
+
+impl SearchOptions {
+    fn for_program(program: &[Instruction]) -> Self {
+        let input_length = program.iter().filter(|instruction| matches!(instruction, Instruction::Inp(_))).count();
+        SearchOptions { input_length, alphabet: (1..=9).collect() }
+    }
+}
+
+fn split_blocks(program: &[Instruction]) -> (&[Instruction], Vec<&[Instruction]>) {
+    let mut starts: Vec<usize> = program
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| matches!(instruction, Instruction::Inp(_)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let prelude = &program[..*starts.first().unwrap_or(&program.len())];
+    starts.push(program.len());
+
+    (prelude, starts.windows(2).map(|w| &program[w[0]..w[1]]).collect())
+}
+
+fn live_registers(blocks: &[&[Instruction]]) -> Vec<[bool; 4]> {
+    let mut live = vec![[false; 4]; blocks.len() + 1];
+    live[blocks.len()][Register::Z as usize] = true;
+
+    for (i, block) in blocks.iter().enumerate().rev() {
+        live[i] = live[i + 1];
+
+        for instruction in block.iter().rev() {
+            live[i][instruction.target() as usize] = false;
+
+            for register in instruction.reads() {
+                live[i][register as usize] = true;
+            }
+        }
+    }
+
+    live
+}
+
+struct Search<'a> {
+    blocks: Vec<&'a [Instruction]>,
+    live: Vec<[bool; 4]>,
+    alphabet: Vec<i64>,
+    failed: HashSet<(usize, Registers)>,
+}
+
+impl Search<'_> {
+    fn find(&mut self, index: usize, registers: Registers, digits: &mut Vec<i64>) -> bool {
+        if index == self.blocks.len() {
+            return registers[Register::Z as usize] == 0;
+        }
+
+        let mut key = registers;
+        for (register, live) in key.iter_mut().zip(self.live[index]) {
+            if !live {
+                *register = 0;
+            }
+        }
+
+        if self.failed.contains(&(index, key)) {
+            return false;
+        }
+
+        for digit in self.alphabet.clone() {
+            let mut next = registers;
+            let mut input = [digit].into_iter();
+
+            if self.blocks[index].iter().all(|instruction| execute(instruction, &mut next, &mut input).is_ok()) {
+                digits.push(digit);
+
+                if self.find(index + 1, next, digits) {
+                    return true;
+                }
+
+                digits.pop();
+            }
+        }
+
+        self.failed.insert((index, key));
+
+        false
+    }
+}
+
+fn search(program: &[Instruction], options: &SearchOptions, largest: bool) -> Result<Option<Vec<i64>>, Day24Error> {
+    let (prelude, blocks) = split_blocks(program);
+
+    if blocks.len() != options.input_length {
+        return Err(Day24Error::InputLengthMismatch(options.input_length, blocks.len()));
+    }
+
+    let mut registers = [0; 4];
+    for instruction in prelude {
+        execute(instruction, &mut registers, &mut std::iter::empty())?;
+    }
+
+    let mut alphabet = options.alphabet.clone();
+    alphabet.sort_unstable();
+    if largest {
+        alphabet.reverse();
+    }
+
+    let live = live_registers(&blocks);
+    let mut search = Search { blocks, live, alphabet, failed: HashSet::new() };
+    let mut digits = vec![];
+
+    match search.find(0, registers, &mut digits) {
+        true => Ok(Some(digits)),
+        false => Ok(None),
+    }
+}
+
+/*
+ * For inspecting what a program computes, it can also be run symbolically. Every register then holds an
+ * expression over the inputs `i0`, `i1`, ..., and every expression carries the range of values it can
+ * take given the range of the inputs. The ranges are what make most of the simplification possible,
+ * e.g. `x % 26` is just `x` if `x` is known to be in `0..26`, and `x == w` is `0` if the ranges of `x`
+ * and `w` don't overlap, which is how the `if` in every pushing MONAD section disappears.
+ */
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Expression {
+    Literal(i64),
+    Input(usize),
+    Operation(Rc<Operation>),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Operation {
+    operator: Operator,
+    lhs: Expression,
+    rhs: Expression,
+    range: (i64, i64),
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Expression::Literal(literal) => write!(f, "{}", literal),
+            Expression::Input(index) => write!(f, "i{}", index),
+            Expression::Operation(operation) => write!(f, "({} {} {})", operation.lhs, operation.operator, operation.rhs),
+        }
+    }
+}
+
+impl Expression {
+    fn range(&self, input_range: (i64, i64)) -> (i64, i64) {
+        match self {
+            Expression::Literal(literal) => (*literal, *literal),
+            Expression::Input(_) => input_range,
+            Expression::Operation(operation) => operation.range,
+        }
+    }
+
+    fn split_multiple(&self, m: i64, input_range: (i64, i64)) -> Option<(Expression, Expression)> {
+        let Expression::Operation(sum) = self else { return None };
+        let Expression::Operation(product) = &sum.lhs else { return None };
+        let (remainder_min, remainder_max) = sum.rhs.range(input_range);
+
+        match sum.operator == Operator::Add && product.operator == Operator::Mul && product.rhs == Expression::Literal(m)
+            && product.range.0 >= 0 && remainder_min >= 0 && remainder_max < m {
+            true => Some((product.lhs.clone(), sum.rhs.clone())),
+            false => None,
+        }
+    }
+
+    fn simplify(operator: Operator, lhs: Expression, rhs: Expression, input_range: (i64, i64)) -> Result<Expression, Day24Error> {
+        let (lhs_min, lhs_max) = lhs.range(input_range);
+        let (rhs_min, rhs_max) = rhs.range(input_range);
+
+        if let (Operator::Div | Operator::Mod, Expression::Literal(m)) = (operator, &rhs) {
+            if let Some((quotient, remainder)) = lhs.split_multiple(*m, input_range) {
+                return Ok(match operator {
+                    Operator::Div => quotient,
+                    _ => remainder,
+                });
+            }
+        }
+
+        match (operator, &lhs, &rhs) {
+            (_, Expression::Literal(a), Expression::Literal(b)) => return Ok(Expression::Literal(operator.apply(*a, *b)?)),
+            (Operator::Div | Operator::Mod, _, Expression::Literal(0)) => return Err(Day24Error::DivisionByZero),
+            (Operator::Add, Expression::Literal(0), _) | (Operator::Mul, Expression::Literal(1), _) => return Ok(rhs),
+            (Operator::Add, _, Expression::Literal(0)) | (Operator::Mul | Operator::Div, _, Expression::Literal(1)) => return Ok(lhs),
+            (Operator::Mul, Expression::Literal(0), _) | (Operator::Mul, _, Expression::Literal(0)) => return Ok(Expression::Literal(0)),
+            (Operator::Div, _, Expression::Literal(b)) if lhs_min >= 0 && lhs_max < *b => return Ok(Expression::Literal(0)),
+            (Operator::Mod, _, Expression::Literal(b)) if lhs_min >= 0 && lhs_max < *b => return Ok(lhs),
+            (Operator::Eql, _, _) if lhs_max < rhs_min || rhs_max < lhs_min => return Ok(Expression::Literal(0)),
+            (Operator::Eql, _, _) if lhs == rhs => return Ok(Expression::Literal(1)),
+            _ => {},
+        }
+
+        let range = operator.range((lhs_min, lhs_max), (rhs_min, rhs_max));
+
+        if range.0 == range.1 {
+            return Ok(Expression::Literal(range.0));
+        }
+
+        Ok(Expression::Operation(Rc::new(Operation { operator, lhs, rhs, range })))
+    }
+}
+
+fn symbolic(program: &[Instruction], input_range: (i64, i64)) -> Result<[Expression; 4], Day24Error> {
+    let mut registers = [Expression::Literal(0), Expression::Literal(0), Expression::Literal(0), Expression::Literal(0)];
+    let mut num_inputs = 0;
+
+    for instruction in program {
+        registers[instruction.target() as usize] = match instruction.operation() {
+            None => {
+                num_inputs += 1;
+                Expression::Input(num_inputs - 1)
+            },
+            Some((operator, a, b)) => {
+                let rhs = match b {
+                    Operand::Register(register) => registers[register as usize].clone(),
+                    Operand::Literal(literal) => Expression::Literal(literal),
+                };
+
+                Expression::simplify(operator, registers[a as usize].clone(), rhs, input_range)?
+            },
+        };
+    }
+
+    Ok(registers)
+}
+
+/*
+ * The registers share most of their subexpressions, so printing each of them as a tree repeats the
+ * shared parts and can grow exponentially with the length of the program. Instead, every operation
+ * reachable from more than one place is printed once as a numbered binding and referred to by name.
+ */
+
+fn count_uses(expression: &Expression, uses: &mut HashMap<*const Operation, usize>) {
+    if let Expression::Operation(operation) = expression {
+        let count = uses.entry(Rc::as_ptr(operation)).or_insert(0);
+        *count += 1;
+
+        if *count == 1 {
+            count_uses(&operation.lhs, uses);
+            count_uses(&operation.rhs, uses);
+        }
+    }
+}
+
+fn bind(expression: &Expression, uses: &HashMap<*const Operation, usize>, names: &mut HashMap<*const Operation, String>, lines: &mut Vec<String>) -> String {
+    let Expression::Operation(operation) = expression else { return expression.to_string() };
+    let key = Rc::as_ptr(operation);
+
+    if let Some(name) = names.get(&key) {
+        return name.clone();
+    }
+
+    let lhs = bind(&operation.lhs, uses, names, lines);
+    let rhs = bind(&operation.rhs, uses, names, lines);
+    let printed = format!("({} {} {})", lhs, operation.operator, rhs);
+
+    match uses[&key] {
+        1 => printed,
+        _ => {
+            let name = format!("t{}", names.len());
+            lines.push(format!("{} = {}", name, printed));
+            names.insert(key, name.clone());
+            name
+        },
+    }
+}
+
+fn print_shared(registers: &[(&str, &Expression)]) -> String {
+    let mut uses = HashMap::new();
+    let mut names = HashMap::new();
+    let mut lines = vec![];
+
+    for (_, expression) in registers {
+        count_uses(expression, &mut uses);
+    }
+
+    for (register, expression) in registers {
+        let printed = bind(expression, &uses, &mut names, &mut lines);
+        lines.push(format!("{} = {}", register, printed));
+    }
+
+    lines.join("\n")
+}
+
+fn trace(program: &[Instruction], input: &[i64]) -> Result<String, Day24Error> {
+    let mut registers = [0; 4];
+    let mut input = input.iter().copied();
+    let mut lines = vec![];
+
+    for (i, instruction) in program.iter().enumerate() {
+        execute(instruction, &mut registers, &mut input)?;
+        lines.push(format!(
+            "{:>4}  {:<10}  w={} x={} y={} z={}",
+            i, instruction.to_string(), registers[0], registers[1], registers[2], registers[3]
+        ));
+    }
+
+    Ok(lines.join("\n"))
+}
+
+fn solve(program: &[Instruction], largest: bool) -> Result<Vec<i64>, Day24Error> {
+    let digits = match monad_constants(program) {
+        Some(constants) => solve_monad(&constants, largest),
+        None => search(program, &SearchOptions::for_program(program), largest)?,
+    };
+
+    digits.ok_or(Day24Error::NoValidInput)
+}
+
 #[aoc(day24, part1)]
-fn part1(_: &()) -> i64 {
-    /*
-     * input[ 0] = 9
-     * input[ 1] = 9
-     * input[ 2] = 2
-     * input[ 3] = 9
-     * input[ 4] = 9
-     * input[ 5] = 5
-     * input[ 6] = 1
-     * input[ 7] = 3
-     * input[ 8] = 8
-     * input[ 9] = 9
-     * input[10] = 9
-     * input[11] = 9
-     * input[12] = 7
-     * input[13] = 1
-     */
-
-    99299513899971
+fn part1(program: &[Instruction]) -> Result<i64, Day24Error> {
+    Ok(solve(program, true)?.iter().fold(0, |acc, digit| acc * 10 + digit))
+}
+
+#[aoc(day24, part1, trace)]
+fn part1_trace(program: &[Instruction]) -> Result<String, Day24Error> {
+    Ok(format!("\n\n{}", trace(program, &solve(program, true)?)?))
+}
+
+#[aoc(day24, part1, symbolic)]
+fn part1_symbolic(program: &[Instruction]) -> Result<String, Day24Error> {
+    let [w, x, y, z] = symbolic(program, (1, 9))?;
+    Ok(format!("\n\n{}", print_shared(&[("w", &w), ("x", &x), ("y", &y), ("z", &z)])))
 }
 
 #[aoc(day24, part2)]
-fn part2(_: &()) -> i64 {
-    /*
-     * input[ 0] = 9
-     * input[ 1] = 3
-     * input[ 2] = 1
-     * input[ 3] = 8
-     * input[ 4] = 5
-     * input[ 5] = 1
-     * input[ 6] = 1
-     * input[ 7] = 1
-     * input[ 8] = 1
-     * input[ 9] = 2
-     * input[10] = 7
-     * input[11] = 9
-     * input[12] = 1
-     * input[13] = 1
-     */
-
-    93185111127911
+fn part2(program: &[Instruction]) -> Result<i64, Day24Error> {
+    Ok(solve(program, false)?.iter().fold(0, |acc, digit| acc * 10 + digit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monad(constants: &[(i64, i64, i64)]) -> Vec<Instruction> {
+        let program = constants
+            .iter()
+            .map(|(a, b, c)| format!("inp w\nmul x 0\nadd x z\nmod x 26\ndiv z {}\nadd x {}\neql x w\neql x 0\nmul y 0\nadd y 25\nmul y x\nadd y 1\nmul z y\nmul y 0\nadd y w\nadd y {}\nmul y x\nadd z y", a, b, c))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        parse(&program).unwrap()
+    }
+
+    #[test]
+    fn execute_example1() {
+        let program = parse(include_str!("../input/2021/day24.part1.test.example1.txt")).unwrap();
+        let mut registers = [0; 4];
+        let mut input = [13].into_iter();
+
+        for instruction in &program {
+            execute(instruction, &mut registers, &mut input).unwrap();
+        }
+
+        assert_eq!([1, 1, 0, 1], registers);
+    }
+
+    #[test]
+    fn search_generic_program() {
+        let program = parse("inp z\ninp w\nadd w 3\nmul w -1\nadd z w").unwrap();
+        let options = SearchOptions { input_length: 2, alphabet: (1..=9).collect() };
+
+        assert_eq!(Some(vec![9, 6]), search(&program, &options, true).unwrap());
+        assert_eq!(Some(vec![4, 1]), search(&program, &options, false).unwrap());
+    }
+
+    #[test]
+    fn solve_generic_program() {
+        let program = parse("inp z\ninp w\nadd w 3\nmul w -1\nadd z w").unwrap();
+
+        assert_eq!(SearchOptions { input_length: 2, alphabet: (1..=9).collect() }, SearchOptions::for_program(&program));
+        assert_eq!(96, part1(&program).unwrap());
+        assert_eq!(41, part2(&program).unwrap());
+    }
+
+    #[test]
+    fn search_input_length_mismatch() {
+        let program = parse("inp z\ninp w").unwrap();
+        let options = SearchOptions { input_length: 3, alphabet: (1..=9).collect() };
+
+        assert!(matches!(search(&program, &options, true), Err(Day24Error::InputLengthMismatch(3, 2))));
+    }
+
+    #[test]
+    fn search_matches_monad_solver() {
+        let program = monad(&[(1, 12, 4), (1, 11, 2), (26, -3, 7), (1, 14, 5), (26, -8, 1), (26, -5, 9)]);
+        let constants = monad_constants(&program).unwrap();
+        let options = SearchOptions { input_length: 6, alphabet: (1..=9).collect() };
+
+        assert_eq!(solve_monad(&constants, true), search(&program, &options, true).unwrap());
+        assert_eq!(solve_monad(&constants, false), search(&program, &options, false).unwrap());
+    }
+
+    #[test]
+    fn no_valid_input() {
+        let program = monad(&[(1, 12, 4), (26, -20, 1)]);
+
+        assert!(matches!(part1(&program), Err(Day24Error::NoValidInput)));
+        assert!(matches!(part2(&program), Err(Day24Error::NoValidInput)));
+    }
+
+    #[test]
+    fn symbolic_example1() {
+        let program = parse(include_str!("../input/2021/day24.part1.test.example1.txt")).unwrap();
+        let [w, x, y, z] = symbolic(&program, (0, 15)).unwrap();
+
+        assert_eq!("(((i0 / 2) / 2) / 2)", w.to_string());
+        assert_eq!("(((i0 / 2) / 2) % 2)", x.to_string());
+        assert_eq!("((i0 / 2) % 2)", y.to_string());
+        assert_eq!("(i0 % 2)", z.to_string());
+    }
+
+    #[test]
+    fn symbolic_shared_bindings() {
+        let program = parse(include_str!("../input/2021/day24.part1.test.example1.txt")).unwrap();
+
+        assert_eq!(
+            "\n\nt0 = (i0 / 2)\nt1 = (t0 / 2)\nw = (t1 / 2)\nx = (t1 % 2)\ny = (t0 % 2)\nz = (i0 % 2)",
+            part1_symbolic(&program).unwrap()
+        );
+
+        let doubled = parse(&format!("inp z\n{}", "add z z\n".repeat(40))).unwrap();
+        let printed = print_shared(&[("z", &symbolic(&doubled, (1, 9)).unwrap()[3])]);
+
+        assert_eq!(40, printed.lines().count());
+        assert_eq!("z = (t38 + t38)", printed.lines().last().unwrap());
+    }
+
+    #[test]
+    fn symbolic_monad_push_and_pop() {
+        let [_, _, _, z] = symbolic(&monad(&[(1, 12, 4)]), (1, 9)).unwrap();
+        assert_eq!("(i0 + 4)", z.to_string());
+
+        let [_, _, _, z] = symbolic(&monad(&[(1, 12, 4), (1, 11, 2)]), (1, 9)).unwrap();
+        assert_eq!("(((i0 + 4) * 26) + (i1 + 2))", z.to_string());
+
+        let [_, x, _, _] = symbolic(&monad(&[(1, 12, 4), (1, 11, 2), (26, -3, 7)]), (1, 9)).unwrap();
+        assert_eq!("((((i1 + 2) + -3) == i2) == 0)", x.to_string());
+    }
+
+    #[test]
+    fn overflow() {
+        let program = parse("inp z\nmul z 999999999\nmul z 999999999\nmul z 999999999").unwrap();
+        let options = SearchOptions::for_program(&program);
+
+        assert_eq!(None, search(&program, &options, true).unwrap());
+        assert!(matches!(part1(&program), Err(Day24Error::NoValidInput)));
+        assert!(matches!(execute(&program[1], &mut [0, 0, 0, i64::MAX], &mut std::iter::empty()), Err(Day24Error::Overflow(i64::MAX, Operator::Mul, 999999999))));
+        assert!(matches!(parse("inp z\nmul z q"), Err(Day24Error::InvalidRegister(_))));
+    }
+
+    #[test]
+    fn symbolic_division_by_zero() {
+        assert!(matches!(symbolic(&parse("inp x\ndiv x 0").unwrap(), (1, 9)), Err(Day24Error::DivisionByZero)));
+    }
+
+    #[test]
+    fn trace_example1() {
+        let program = parse(include_str!("../input/2021/day24.part1.test.example1.txt")).unwrap();
+        let trace = trace(&program, &[6]).unwrap();
+
+        assert_eq!(program.len(), trace.lines().count());
+        assert_eq!("   0  inp w       w=6 x=0 y=0 z=0", trace.lines().next().unwrap());
+        assert_eq!("  10  mod w 2     w=0 x=1 y=1 z=0", trace.lines().last().unwrap());
+    }
 }
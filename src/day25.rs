@@ -1,6 +1,11 @@
-use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::stdout;
+use std::time::Duration;
 use aoc_runner_derive::{aoc, aoc_generator};
 use crate::day25::Direction::{East, South};
+use crate::animation::{write_frames, Frame, Output};
+use crate::automaton::{evolve, run, Automaton, Boundary, Neighborhood, Stop};
+use crate::grid::{Grid, Position};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum Direction {
@@ -9,71 +14,320 @@ enum Direction {
 }
 
 impl Direction {
-    fn neighbor(&self, (i, j): (usize, usize), (height, width): (usize, usize)) -> (usize, usize) {
+    fn delta(&self) -> (isize, isize) {
         match self {
-            East => (i, (j + 1) % width),
-            South => ((i + 1) % height, j),
+            East => (0, 1),
+            South => (1, 0),
         }
     }
 }
 
+type SeaFloor = Grid<Option<Direction>>;
+
 #[aoc_generator(day25)]
-fn parse(input: &str) -> (HashMap<(usize, usize), Direction>, (usize, usize)) {
-    let height = input.lines().count();
-    let width = input.lines().next().unwrap().len();
-
-    let map = input
-        .lines()
-        .flat_map(|line| line.chars())
-        .enumerate()
-        .filter_map(|(i, c)| match c {
-            '>' => Some(((i / width, i % width), East)),
-            'v' => Some(((i / width, i % width), South)),
-            '.' => None,
-            _ => panic!(),
+fn parse(input: &str) -> SeaFloor {
+    Grid::parse_with(input, |c| match c {
+        '>' => Some(Some(East)),
+        'v' => Some(Some(South)),
+        '.' => Some(None),
+        _ => None,
+    }).unwrap()
+}
+
+/*
+ * Each herd moves as a local rule looking one cell behind and one cell ahead along its direction: a cucumber
+ * leaves a cell whose next cell is free, and one arrives in a free cell whose previous cell holds one.
+ */
+fn move_herd(map: &Automaton<Option<Direction>>, direction: Direction) -> Automaton<Option<Direction>> {
+    let (di, dj) = direction.delta();
+    let neighborhood = Neighborhood::new([(-di, -dj), (di, dj)]);
+
+    map.apply(&neighborhood, |cell, neighbors| match (cell, neighbors) {
+        (None, [behind, _]) if *behind == Some(direction) => Some(direction),
+        (Some(herd), [_, None]) if *herd == direction => None,
+        _ => *cell,
+    })
+}
+
+fn step(map: &Automaton<Option<Direction>>) -> Automaton<Option<Direction>> {
+    move_herd(&move_herd(map, East), South)
+}
+
+/*
+ * The dense representation keeps one bit per cell for each herd, rows padded to whole words. The east-facing
+ * herd moves by rotating rows a bit at a time, while the south-facing herd moves whole rows of words at once.
+ */
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Herds {
+    height: usize,
+    width: usize,
+    words_per_row: usize,
+    east: Vec<u64>,
+    south: Vec<u64>,
+}
+
+/*
+ * Moves every bit of the row one column right, the last column wrapping around to the first.
+ */
+fn rotate_right(row: &[u64], width: usize) -> Vec<u64> {
+    let mut carry = row[(width - 1) / 64] >> ((width - 1) % 64) & 1;
+    let mut rotated: Vec<u64> = row
+        .iter()
+        .map(|word| {
+            let shifted = word << 1 | carry;
+            carry = word >> 63;
+            shifted
         })
         .collect();
 
-    (map, (height, width))
+    if !width.is_multiple_of(64) {
+        *rotated.last_mut().unwrap() &= (1 << (width % 64)) - 1;
+    }
+
+    rotated
 }
 
-#[aoc(day25, part1)]
-fn part1((map, dimensions): &(HashMap<(usize, usize), Direction>, (usize, usize))) -> usize {
-    let mut map = map.clone();
-    let mut steps = 0;
+/*
+ * Moves every bit of the row one column left, the first column wrapping around to the last.
+ */
+fn rotate_left(row: &[u64], width: usize) -> Vec<u64> {
+    let mut carry = 0;
+    let mut rotated = vec![0; row.len()];
+
+    for (k, word) in row.iter().enumerate().rev() {
+        rotated[k] = word >> 1 | carry << 63;
+        carry = word & 1;
+    }
+
+    rotated[(width - 1) / 64] |= (row[0] & 1) << ((width - 1) % 64);
+    rotated
+}
+
+impl Herds {
+    fn row(bits: &[u64], words_per_row: usize, i: usize) -> &[u64] {
+        &bits[i * words_per_row..(i + 1) * words_per_row]
+    }
+
+    fn get(&self, (i, j): Position) -> Option<Direction> {
+        let (word, bit) = (i * self.words_per_row + j / 64, j % 64);
+
+        match (self.east[word] >> bit & 1, self.south[word] >> bit & 1) {
+            (1, _) => Some(East),
+            (_, 1) => Some(South),
+            _ => None,
+        }
+    }
 
-    loop {
+    /*
+     * The state after one step, and whether any sea cucumber moved during it.
+     */
+    fn step(&self) -> (Herds, bool) {
+        let words_per_row = self.words_per_row;
         let mut moved = false;
+        let mut east = Vec::with_capacity(self.east.len());
 
-        for current_direction in [ East, South ] {
-            let mut next_map = HashMap::new();
-            for (&position, &direction) in &map {
-                let neighbor = direction.neighbor(position, *dimensions);
-
-                if direction != current_direction || map.contains_key(&neighbor) || next_map.contains_key(&neighbor) {
-                    next_map.insert(position, direction);
-                } else {
-                    next_map.insert(neighbor, direction);
-                    moved = true;
-                }
+        for i in 0..self.height {
+            let herd = Herds::row(&self.east, words_per_row, i);
+            let occupied: Vec<u64> = herd.iter().zip(Herds::row(&self.south, words_per_row, i)).map(|(e, s)| e | s).collect();
+            let ahead = rotate_left(&occupied, self.width);
+            let movers: Vec<u64> = herd.iter().zip(&ahead).map(|(e, a)| e & !a).collect();
+            let arrivals = rotate_right(&movers, self.width);
+
+            moved |= movers.iter().any(|word| *word != 0);
+            east.extend(herd.iter().zip(&movers).zip(&arrivals).map(|((e, m), a)| e & !m | a));
+        }
+
+        let occupied: Vec<u64> = east.iter().zip(&self.south).map(|(e, s)| e | s).collect();
+        let movers: Vec<u64> = (0..self.height)
+            .flat_map(|i| {
+                let ahead = Herds::row(&occupied, words_per_row, (i + 1) % self.height);
+                Herds::row(&self.south, words_per_row, i).iter().zip(ahead).map(|(s, a)| s & !a)
+            })
+            .collect();
+
+        moved |= movers.iter().any(|word| *word != 0);
+
+        let south = (0..self.height)
+            .flat_map(|i| {
+                let arrivals = Herds::row(&movers, words_per_row, (i + self.height - 1) % self.height);
+                Herds::row(&self.south, words_per_row, i)
+                    .iter()
+                    .zip(Herds::row(&movers, words_per_row, i))
+                    .zip(arrivals)
+                    .map(|((s, m), a)| s & !m | a)
+            })
+            .collect();
+
+        (Herds { east, south, ..*self }, moved)
+    }
+
+    fn states(&self) -> impl Iterator<Item = Herds> {
+        evolve(self.clone(), |herds| herds.step().0)
+    }
+
+    fn run(&self, steps: usize) -> Herds {
+        self.states().nth(steps).unwrap()
+    }
+
+    /*
+     * The number of the first step during which no sea cucumber moves.
+     */
+    fn first_still_step(&self) -> usize {
+        let mut herds = self.clone();
+
+        for step in 1.. {
+            let (next, moved) = herds.step();
+
+            if !moved {
+                return step;
             }
-            map = next_map;
+
+            herds = next;
         }
 
-        steps += 1;
+        unreachable!()
+    }
+}
 
-        if !moved {
-            return steps;
+impl From<&SeaFloor> for Herds {
+    fn from(map: &SeaFloor) -> Self {
+        let words_per_row = map.width().div_ceil(64);
+        let mut east = vec![0; map.height() * words_per_row];
+        let mut south = vec![0; map.height() * words_per_row];
+
+        for ((i, j), cell) in map.iter() {
+            let (word, bit) = (i * words_per_row + j / 64, j % 64);
+
+            match cell {
+                Some(East) => east[word] |= 1 << bit,
+                Some(South) => south[word] |= 1 << bit,
+                None => (),
+            }
         }
+
+        Herds { height: map.height(), width: map.width(), words_per_row, east, south }
     }
 }
 
+impl Display for Herds {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for i in 0..self.height {
+            for j in 0..self.width {
+                write!(f, "{}", self.glyph((i, j)))?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Frame for Herds {
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn glyph(&self, position: Position) -> char {
+        match self.get(position) {
+            Some(East) => '>',
+            Some(South) => 'v',
+            None => '.',
+        }
+    }
+
+    fn max_value(&self) -> u32 {
+        2
+    }
+
+    fn value(&self, position: Position) -> u32 {
+        match self.get(position) {
+            Some(East) => 2,
+            Some(South) => 1,
+            None => 0,
+        }
+    }
+}
+
+#[aoc(day25, part1)]
+fn part1(map: &SeaFloor) -> usize {
+    Herds::from(map).first_still_step()
+}
+
+#[aoc(day25, part1, settled)]
+fn part1_settled(map: &SeaFloor) -> String {
+    let herds = Herds::from(map);
+    format!("\n\n{}", herds.run(herds.first_still_step()))
+}
+
+#[aoc(day25, part1, automaton)]
+fn part1_automaton(map: &SeaFloor) -> usize {
+    let (steps, _) = run(Automaton::new(map.clone(), Boundary::Wrapping), step, Stop::NoChange);
+    steps
+}
+
+#[aoc(day25, part1, playback)]
+fn part1_playback(map: &SeaFloor) -> usize {
+    let herds = Herds::from(map);
+    let steps = herds.first_still_step();
+    write_frames(&mut stdout(), herds.states(), 0..=steps, Output::Playback(Duration::from_millis(20))).unwrap();
+    steps
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::animation::frames_to_string;
 
     #[test]
     fn part1_example() {
         assert_eq!(58, part1(&parse(include_str!("../input/2021/day25.part1.test.58.txt"))));
+        assert_eq!(58, part1_automaton(&parse(include_str!("../input/2021/day25.part1.test.58.txt"))));
+    }
+
+    #[test]
+    fn text_frames() {
+        let map = parse(include_str!("../input/2021/day25.part1.test.58.txt"));
+        let expected = "After step 1:
+....>.>v.>
+v.v>.>v.v.
+>v>>..>v..
+>>v>v>.>.v
+.>v.v...v.
+v>>.>vvv..
+..v...>>..
+vv...>>vv.
+>.v.v..v.v
+
+";
+
+        assert_eq!(expected, frames_to_string(Herds::from(&map).states(), 1..=1, Output::Text));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn run_exact_steps() {
+        let herds = Herds::from(&parse("...>...\n.......\n......>\nv.....>\n......>\n.......\n..vvv.."));
+
+        assert_eq!(">......\n..v....\n..>.v..\n.>.v...\n...>...\n.......\nv......\n", herds.run(4).to_string());
+        assert_eq!(herds.run(4), herds.run(2).run(2));
+    }
+
+    #[test]
+    fn matches_automaton_across_word_boundaries() {
+        let map = Grid::from_fn(7, 130, |(i, j)| match (i * 31 + j * 17) % 5 {
+            0 | 3 => Some(East),
+            1 => Some(South),
+            _ => None,
+        });
+
+        let automaton = evolve(Automaton::new(map.clone(), Boundary::Wrapping), step);
+
+        for (herds, automaton) in Herds::from(&map).states().zip(automaton).take(20) {
+            assert_eq!(Herds::from(automaton.grid()), herds);
+        }
+    }
+}
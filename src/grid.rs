@@ -0,0 +1,213 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::{Index, IndexMut};
+
+pub type Position = (usize, usize);
+
+#[derive(Debug)]
+pub enum GridError {
+    Empty,
+    RaggedRow(usize),
+    InvalidCell(char, Position),
+}
+
+impl Display for GridError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GridError::Empty => write!(f, "empty grid"),
+            GridError::RaggedRow(row) => write!(f, "row {} differs in length from the first row", row),
+            GridError::InvalidCell(c, (i, j)) => write!(f, "invalid cell {:?} at ({}, {})", c, i, j),
+        }
+    }
+}
+
+impl Error for GridError {}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Edges {
+    Bounded,
+    Wrapping,
+}
+
+const ORTHOGONAL: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+const SURROUNDING: [(isize, isize); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Grid<T> {
+    height: usize,
+    width: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn from_fn(height: usize, width: usize, mut f: impl FnMut(Position) -> T) -> Self {
+        let cells = (0..height * width)
+            .map(|k| f((k / width, k % width)))
+            .collect();
+
+        Grid { height, width, cells }
+    }
+
+    pub fn parse_with(input: &str, f: impl Fn(char) -> Option<T>) -> Result<Self, GridError> {
+        let width = input.lines().next().ok_or(GridError::Empty)?.chars().count();
+
+        if width == 0 {
+            return Err(GridError::Empty);
+        }
+        let mut cells = vec![];
+        let mut height = 0;
+
+        for (i, line) in input.lines().enumerate() {
+            if line.chars().count() != width {
+                return Err(GridError::RaggedRow(i));
+            }
+
+            for (j, c) in line.chars().enumerate() {
+                cells.push(f(c).ok_or(GridError::InvalidCell(c, (i, j)))?);
+            }
+
+            height += 1;
+        }
+
+        Ok(Grid { height, width, cells })
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get(&self, (i, j): Position) -> Option<&T> {
+        match i < self.height && j < self.width {
+            true => Some(&self.cells[i * self.width + j]),
+            false => None,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Position, &T)> {
+        let width = self.width;
+
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(k, cell)| ((k / width, k % width), cell))
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.cells.iter_mut()
+    }
+
+    pub fn offset(&self, (i, j): Position, (di, dj): (isize, isize), edges: Edges) -> Option<Position> {
+        match edges {
+            Edges::Bounded => {
+                let i = i.checked_add_signed(di).filter(|i| *i < self.height)?;
+                let j = j.checked_add_signed(dj).filter(|j| *j < self.width)?;
+                Some((i, j))
+            },
+            Edges::Wrapping if self.height == 0 || self.width == 0 => None,
+            Edges::Wrapping => Some((
+                (i as isize + di).rem_euclid(self.height as isize) as usize,
+                (j as isize + dj).rem_euclid(self.width as isize) as usize,
+            )),
+        }
+    }
+
+    pub fn neighbors4(&self, position: Position, edges: Edges) -> impl Iterator<Item = Position> + '_ {
+        ORTHOGONAL
+            .iter()
+            .filter_map(move |delta| self.offset(position, *delta, edges))
+    }
+
+    pub fn neighbors8(&self, position: Position, edges: Edges) -> impl Iterator<Item = Position> + '_ {
+        SURROUNDING
+            .iter()
+            .filter_map(move |delta| self.offset(position, *delta, edges))
+    }
+}
+
+impl Grid<u32> {
+    pub fn parse_digits(input: &str) -> Result<Self, GridError> {
+        Grid::parse_with(input, |c| c.to_digit(10))
+    }
+}
+
+impl<T> Index<Position> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, position: Position) -> &Self::Output {
+        self.get(position).unwrap()
+    }
+}
+
+impl<T> IndexMut<Position> for Grid<T> {
+    fn index_mut(&mut self, (i, j): Position) -> &mut Self::Output {
+        assert!(i < self.height && j < self.width);
+        &mut self.cells[i * self.width + j]
+    }
+}
+
+impl<T: Display> Display for Grid<T> {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for i in 0..self.height {
+            for cell in &self.cells[i * self.width..(i + 1) * self.width] {
+                write!(f, "{}", cell)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let grid = Grid::parse_digits("123\n456\n").unwrap();
+
+        assert_eq!((2, 3), (grid.height(), grid.width()));
+        assert_eq!(6, grid[(1, 2)]);
+        assert_eq!(None, grid.get((2, 0)));
+        assert_eq!("123\n456\n", grid.to_string());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(Grid::parse_digits(""), Err(GridError::Empty)));
+        assert!(matches!(Grid::parse_digits("\n"), Err(GridError::Empty)));
+        assert!(matches!(Grid::parse_digits("123\n45"), Err(GridError::RaggedRow(1))));
+        assert!(matches!(Grid::parse_digits("123\n4x6"), Err(GridError::InvalidCell('x', (1, 1)))));
+    }
+
+    #[test]
+    fn bounded_neighbors() {
+        let grid = Grid::from_fn(3, 3, |_| 0);
+
+        assert_eq!(vec![(0, 1), (1, 0)], grid.neighbors4((0, 0), Edges::Bounded).collect::<Vec<_>>());
+        assert_eq!(3, grid.neighbors8((2, 2), Edges::Bounded).count());
+        assert_eq!(8, grid.neighbors8((1, 1), Edges::Bounded).count());
+    }
+
+    #[test]
+    fn wrapping_neighbors() {
+        let grid = Grid::from_fn(3, 4, |_| 0);
+
+        assert_eq!(vec![(2, 0), (0, 3), (0, 1), (1, 0)], grid.neighbors4((0, 0), Edges::Wrapping).collect::<Vec<_>>());
+        assert_eq!(Some((0, 0)), grid.offset((2, 3), (1, 1), Edges::Wrapping));
+        assert_eq!(None, grid.offset((2, 3), (1, 1), Edges::Bounded));
+    }
+
+    #[test]
+    fn zero_width() {
+        let grid = Grid::from_fn(2, 0, |_| 0);
+
+        assert_eq!("\n\n", grid.to_string());
+        assert_eq!(None, grid.offset((0, 0), (1, 1), Edges::Wrapping));
+    }
+}
@@ -26,4 +26,9 @@ mod day23;
 mod day24;
 mod day25;
 
+mod animation;
+mod automaton;
+mod grid;
+mod netpbm;
+
 aoc_lib!{ year = 2021 }
@@ -0,0 +1,101 @@
+use std::fs;
+use std::io;
+use crate::grid::Position;
+
+/*
+ * Writers for the plain (ASCII) variants of the Netpbm formats, which any ordinary image viewer can open.
+ * The spec asks for lines of at most 70 characters, so samples are wrapped rather than written a row per line.
+ */
+const MAX_LINE_LENGTH: usize = 70;
+
+fn wrap(header: String, samples: impl Iterator<Item = String>, separator: &str) -> String {
+    let mut output = header;
+    let mut line_length = 0;
+
+    for sample in samples {
+        if line_length > 0 && line_length + separator.len() + sample.len() > MAX_LINE_LENGTH {
+            output.push('\n');
+            line_length = 0;
+        } else if line_length > 0 {
+            output.push_str(separator);
+            line_length += separator.len();
+        }
+
+        line_length += sample.len();
+        output.push_str(&sample);
+    }
+
+    output.push('\n');
+    output
+}
+
+fn positions(height: usize, width: usize) -> impl Iterator<Item = Position> {
+    (0..height).flat_map(move |i| (0..width).map(move |j| (i, j)))
+}
+
+/*
+ * Plain PBM (P1) bitmap, where set pixels are drawn black.
+ */
+pub fn pbm(height: usize, width: usize, is_set: impl Fn(Position) -> bool) -> String {
+    let samples = positions(height, width).map(|position| match is_set(position) {
+        true => "1".to_string(),
+        false => "0".to_string(),
+    });
+
+    wrap(format!("P1\n{} {}\n", width, height), samples, "")
+}
+
+/*
+ * Plain PGM (P2) graymap with values from 0 (black) up to `max_value` (white).
+ */
+pub fn pgm(height: usize, width: usize, max_value: u32, value: impl Fn(Position) -> u32) -> String {
+    let samples = positions(height, width).map(|position| value(position).min(max_value).to_string());
+
+    wrap(format!("P2\n{} {}\n{}\n", width, height, max_value), samples, " ")
+}
+
+/*
+ * Runners export images to a file of their own rather than printing them along with their answer, and
+ * report where the image went instead.
+ */
+pub fn save(path: &str, image: &str) -> io::Result<String> {
+    fs::write(path, image)?;
+    Ok(format!("{} bytes written to {}", image.len(), path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_bitmap() {
+        assert_eq!("P1\n3 2\n100010\n", pbm(2, 3, |(i, j)| i == j));
+    }
+
+    #[test]
+    fn small_graymap() {
+        assert_eq!("P2\n2 2\n9\n0 1 9 9\n", pgm(2, 2, 9, |(i, j)| (i * 10 + j) as u32));
+    }
+
+    #[test]
+    fn long_lines_are_wrapped() {
+        let bitmap = pbm(2, 100, |_| true);
+        let graymap = pgm(3, 50, 255, |_| 255);
+
+        assert!(bitmap.lines().chain(graymap.lines()).all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert_eq!(200, bitmap.lines().skip(2).map(|line| line.len()).sum::<usize>());
+        assert_eq!(150, graymap.lines().skip(3).flat_map(|line| line.split(' ')).count());
+    }
+
+    #[test]
+    fn save_to_file() {
+        let path = std::env::temp_dir().join("netpbm_save_to_file.pbm");
+        let path = path.to_str().unwrap();
+        let bitmap = pbm(2, 3, |(i, j)| i == j);
+
+        assert_eq!(format!("14 bytes written to {}", path), save(path, &bitmap).unwrap());
+        assert_eq!(bitmap, fs::read_to_string(path).unwrap());
+
+        fs::remove_file(path).unwrap();
+    }
+}